@@ -1,25 +1,164 @@
+use std::fmt;
+
 use crate::{
     mat::Matrix,
+    srgb,
     vec::{Vector2, Vector3},
 };
 
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    /// 不透明度，255为完全不透明
+    pub a: u8,
+}
+
+/// 线性空间中的浮点颜色，rgb可以超过1，用于光照计算和HDR渲染
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    /// 不透明度，1为完全不透明
+    pub a: f32,
 }
 
-impl Color {
+impl LinearColor {
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// 由取值范围0~1的sRGB分量解码，不透明
+    pub fn from_srgb(v: Vector3<f32>) -> Self {
+        let c = srgb::to_linear_f32;
+        Self::new(c(v.x()), c(v.y()), c(v.z()), 1.0)
+    }
+
+    /// 透明度测试，不透明度低于阈值时返回None，即丢弃该像素
+    pub fn alpha_test(self, cutoff: f32) -> Option<Self> {
+        (self.a >= cutoff).then_some(self)
+    }
+
+    /// rgb乘以系数，不透明度不变
     pub fn scale(self, fx: f32) -> Self {
         Self {
-            r: (fx * self.r as f32) as u8,
-            g: (fx * self.g as f32) as u8,
-            b: (fx * self.b as f32) as u8,
+            r: self.r * fx,
+            g: self.g * fx,
+            b: self.b * fx,
+            a: self.a,
+        }
+    }
+
+    /// 以self为源颜色、dst为目标颜色按mode混合，结果不截断
+    pub fn blend(self, dst: LinearColor, mode: BlendMode) -> Self {
+        let a = self.a.clamp(0.0, 1.0);
+        let mix = |s: f32, d: f32| match mode {
+            BlendMode::Alpha => s * a + d * (1.0 - a),
+            BlendMode::Additive => s * a + d,
+            BlendMode::Premultiplied => s + d * (1.0 - a),
+        };
+        Self {
+            r: mix(self.r, dst.r),
+            g: mix(self.g, dst.g),
+            b: mix(self.b, dst.b),
+            a: a + dst.a.clamp(0.0, 1.0) * (1.0 - a),
+        }
+    }
+
+    /// 对rgb分别应用f
+    fn map_rgb(self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            r: f(self.r),
+            g: f(self.g),
+            b: f(self.b),
+            a: self.a,
+        }
+    }
+}
+
+/// 解码sRGB
+impl From<Color> for LinearColor {
+    fn from(c: Color) -> Self {
+        Self {
+            r: srgb::to_linear(c.r),
+            g: srgb::to_linear(c.g),
+            b: srgb::to_linear(c.b),
+            a: unit(c.a),
         }
     }
 }
 
+/// 编码为sRGB，超出0~1的值截断
+impl From<LinearColor> for Color {
+    fn from(c: LinearColor) -> Self {
+        Self {
+            r: srgb::from_linear(c.r),
+            g: srgb::from_linear(c.g),
+            b: srgb::from_linear(c.b),
+            a: (c.a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        }
+    }
+}
+
+fn unit(c: u8) -> f32 {
+    c as f32 / 255.0
+}
+
+/// HDR渲染目标，保存线性空间中不截断的浮点颜色，经色调映射后输出到8位的帧缓冲
+pub type HdrBuffer = FrameBuffer<LinearColor>;
+
+/// 把HDR颜色映射到0~1的色调映射算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// 超过1的部分直接截断
+    Clamp,
+    /// x / (1 + x)
+    Reinhard,
+    /// ACES电影曲线的Narkowicz拟合，对比度更高，高光过渡更自然
+    AcesFilmic,
+    /// 曝光曲线 1 - e^(-x)
+    Exposure,
+}
+
+impl ToneMapping {
+    /// 映射一个线性分量
+    pub fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMapping::Clamp => x.min(1.0),
+            ToneMapping::Reinhard => x / (1.0 + x),
+            ToneMapping::AcesFilmic => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+            ToneMapping::Exposure => 1.0 - (-x).exp(),
+        }
+    }
+}
+
+impl HdrBuffer {
+    /// 色调映射后编码为sRGB写入target，ev为曝光补偿，每加1亮度翻倍
+    pub fn tone_map(&self, target: &mut FrameBuffer<Color>, mapping: ToneMapping, ev: f32) {
+        let exposure = ev.exp2();
+        let pixels = target.data.iter_mut().zip(&self.data);
+        for (dst, src) in pixels {
+            *dst = src.scale(exposure).map_rgb(|x| mapping.apply(x)).into();
+        }
+    }
+}
+
+/// 半透明像素与已绘制像素的混合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// 按不透明度线性混合：src * a + dst * (1 - a)
+    Alpha,
+    /// 叠加，用于发光、粒子等效果：src * a + dst
+    Additive,
+    /// 源颜色已预乘不透明度：src + dst * (1 - a)
+    Premultiplied,
+}
+
 pub struct Triangle2D {
     // 三个顶点坐标
     pub a: Vector2<i32>,
@@ -36,6 +175,29 @@ pub struct Triangle2D {
 
     // 三个顶点的光照强度
     pub intensity: Vector3<f32>,
+
+    // 三个顶点的颜色，取值范围0~1
+    pub color_a: Vector3<f32>,
+    pub color_b: Vector3<f32>,
+    pub color_c: Vector3<f32>,
+
+    // 三个顶点处切线空间中指向光源的方向，用于法线贴图
+    pub light_a: Vector3<f32>,
+    pub light_b: Vector3<f32>,
+    pub light_c: Vector3<f32>,
+}
+
+/// 三角形内一点插值得到的属性，交给着色函数计算颜色，着色函数返回None时丢弃该像素
+#[derive(Debug, Clone, Copy)]
+pub struct Fragment {
+    pub uv: Vector2<f32>,
+    /// 插值后的顶点颜色
+    pub color: Vector3<f32>,
+    /// 屏幕上x、y方向各移动一个像素时uv的变化量，用于选择mipmap级别
+    pub duv_dx: Vector2<f32>,
+    pub duv_dy: Vector2<f32>,
+    /// 插值后切线空间中指向光源的方向，未归一化
+    pub light: Vector3<f32>,
 }
 
 impl Triangle2D {
@@ -68,6 +230,31 @@ impl Triangle2D {
         Matrix::new([self.uv_a, self.uv_b, self.uv_c]).transpose() * bc
     }
 
+    /// 屏幕上x、y方向各移动一个像素时uv的变化量
+    /// uv在屏幕空间线性插值，整个三角形内都相同
+    pub fn uv_derivatives(&self) -> (Vector2<f32>, Vector2<f32>) {
+        let uv = |p| self.get_uv(self.barycentric(p));
+        let base = uv(self.a);
+        (
+            uv(self.a + Vector2::new([1, 0])) - base,
+            uv(self.a + Vector2::new([0, 1])) - base,
+        )
+    }
+
+    /// 对三角形内部进行顶点颜色插值计算
+    pub fn get_color(&self, bc: Vector3<f32>) -> Vector3<f32> {
+        self.color_a * bc.x() + self.color_b * bc.y() + self.color_c * bc.z()
+    }
+
+    pub fn get_light(&self, bc: Vector3<f32>) -> Vector3<f32> {
+        self.light_a * bc.x() + self.light_b * bc.y() + self.light_c * bc.z()
+    }
+
+    /// 三个顶点的平均深度，值越大离相机越近，用于半透明面片由远到近排序
+    pub fn mean_depth(&self) -> f32 {
+        (self.depth.x() + self.depth.y() + self.depth.z()) / 3.0
+    }
+
     pub fn get_instensity(&self, bc: Vector3<f32>) -> f32 {
         self.intensity.dot(bc)
     }
@@ -100,6 +287,24 @@ impl Triangle2D {
 pub trait DrawTarget {
     fn get_size(&self) -> (i32, i32);
     fn draw(&mut self, x: i32, y: i32, color: Color);
+    /// 读取已绘制的像素，坐标系与draw相同
+    fn pixel(&self, x: i32, y: i32) -> Color;
+
+    /// 以线性颜色绘制，8位的目标编码为sRGB并截断，HDR目标原样保存
+    fn draw_linear(&mut self, x: i32, y: i32, color: LinearColor) {
+        self.draw(x, y, color.into());
+    }
+
+    /// 以线性颜色读取已绘制的像素
+    fn pixel_linear(&self, x: i32, y: i32) -> LinearColor {
+        self.pixel(x, y).into()
+    }
+
+    /// 将color按mode与已绘制的像素混合
+    fn blend(&mut self, x: i32, y: i32, color: LinearColor, mode: BlendMode) {
+        let dst = self.pixel_linear(x, y);
+        self.draw_linear(x, y, color.blend(dst, mode));
+    }
 
     fn draw_line_float(&mut self, start: Vector2<i32>, end: Vector2<i32>, color: Color) {
         let delta = end - start;
@@ -247,10 +452,11 @@ pub trait DrawTarget {
         &mut self,
         t: Triangle2D,
         zbuffer: &mut FrameBuffer<f32>,
-        texture_getter: impl Fn(Vector2<f32>) -> Color,
+        shader: impl Fn(Fragment) -> Option<LinearColor>,
     ) {
         let (w, h) = self.get_size();
         let (x_min, x_max, y_min, y_max) = t.bounding_box(w, h);
+        let (duv_dx, duv_dy) = t.uv_derivatives();
         for x in x_min..=x_max {
             for y in y_min..=y_max {
                 let p = Vector2::new([x, y]);
@@ -261,15 +467,156 @@ pub trait DrawTarget {
                     continue;
                 }
                 let z = t.get_depth(bc);
+                // 被遮挡的像素无需着色
+                if *zbuffer.get(x, y) >= z {
+                    continue;
+                }
 
-                let color = texture_getter(t.get_uv(bc));
+                // 被丢弃的像素不写入深度
+                let Some(color) = shader(Fragment {
+                    uv: t.get_uv(bc),
+                    color: t.get_color(bc),
+                    duv_dx,
+                    duv_dy,
+                    light: t.get_light(bc),
+                }) else {
+                    continue;
+                };
 
                 let intensity = t.get_instensity(bc);
                 // let color = Color::new(128, 128, 128);
-                if *zbuffer.get(x, y) < z {
-                    zbuffer.set(x, y, z);
-                    self.draw(x, y, color.scale(intensity));
+                zbuffer.set(x, y, z);
+                // 光照在线性空间中计算
+                self.draw_linear(x, y, color.scale(intensity));
+            }
+        }
+    }
+
+    /// 绘制半透明三角形，只与不透明物体的深度比较，不写入深度
+    /// 多个半透明三角形需要由远到近依次绘制，才能得到正确的混合结果
+    fn draw_triangle_blended(
+        &mut self,
+        t: Triangle2D,
+        zbuffer: &FrameBuffer<f32>,
+        mode: BlendMode,
+        shader: impl Fn(Fragment) -> Option<LinearColor>,
+    ) {
+        let (w, h) = self.get_size();
+        let (x_min, x_max, y_min, y_max) = t.bounding_box(w, h);
+        let (duv_dx, duv_dy) = t.uv_derivatives();
+        for x in x_min..=x_max {
+            for y in y_min..=y_max {
+                let bc = t.barycentric(Vector2::new([x, y]));
+                if bc.x() < 0.0 || bc.y() < 0.0 || bc.z() < 0.0 {
+                    continue;
+                }
+                if *zbuffer.get(x, y) >= t.get_depth(bc) {
+                    continue;
                 }
+                let Some(color) = shader(Fragment {
+                    uv: t.get_uv(bc),
+                    color: t.get_color(bc),
+                    duv_dx,
+                    duv_dy,
+                    light: t.get_light(bc),
+                }) else {
+                    continue;
+                };
+                self.blend(x, y, color.scale(t.get_instensity(bc)), mode);
+            }
+        }
+    }
+}
+
+/// 加权混合的顺序无关透明(Weighted Blended OIT)缓冲
+/// 半透明三角形可按任意顺序绘制，互相穿插时也不会出现排序错误，
+/// 结果是按深度加权的近似混合，最后由resolve合成到目标上
+#[derive(Debug)]
+pub struct OitBuffer {
+    /// 加权后的线性颜色与权重之和
+    accum: FrameBuffer<[f32; 4]>,
+    /// 背景透过的比例，即所有(1 - a)之积
+    revealage: FrameBuffer<f32>,
+}
+
+impl OitBuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let mut revealage = FrameBuffer::new(width, height);
+        revealage.fill(1.0);
+        Self {
+            accum: FrameBuffer::new(width, height),
+            revealage,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.accum.clear();
+        self.revealage.fill(1.0);
+    }
+
+    /// 累积一个半透明三角形，深度测试规则与draw_triangle_blended相同
+    /// 深度取值0~1、越大越近，越近的像素权重越大
+    pub fn draw_triangle(
+        &mut self,
+        t: Triangle2D,
+        zbuffer: &FrameBuffer<f32>,
+        shader: impl Fn(Fragment) -> Option<LinearColor>,
+    ) {
+        let (w, h) = (self.accum.get_width(), self.accum.get_height());
+        let (x_min, x_max, y_min, y_max) = t.bounding_box(w, h);
+        let (duv_dx, duv_dy) = t.uv_derivatives();
+        for x in x_min..=x_max {
+            for y in y_min..=y_max {
+                let bc = t.barycentric(Vector2::new([x, y]));
+                if bc.x() < 0.0 || bc.y() < 0.0 || bc.z() < 0.0 {
+                    continue;
+                }
+                let z = t.get_depth(bc);
+                if *zbuffer.get(x, y) >= z {
+                    continue;
+                }
+                let Some(color) = shader(Fragment {
+                    uv: t.get_uv(bc),
+                    color: t.get_color(bc),
+                    duv_dx,
+                    duv_dy,
+                    light: t.get_light(bc),
+                }) else {
+                    continue;
+                };
+                let color = color.scale(t.get_instensity(bc));
+                let a = color.a;
+                let weight = a * (3e3 * z.clamp(0.0, 1.0).powi(3)).clamp(1e-2, 3e3);
+                let [r, g, b, sum] = *self.accum.get(x, y);
+                self.accum.set(
+                    x,
+                    y,
+                    [
+                        r + color.r * weight,
+                        g + color.g * weight,
+                        b + color.b * weight,
+                        sum + weight,
+                    ],
+                );
+                let revealage = *self.revealage.get(x, y);
+                self.revealage.set(x, y, revealage * (1.0 - a));
+            }
+        }
+    }
+
+    /// 将累积结果合成到target上
+    pub fn resolve(&self, target: &mut impl DrawTarget) {
+        let (w, h) = target.get_size();
+        for x in 0..w.min(self.accum.get_width()) {
+            for y in 0..h.min(self.accum.get_height()) {
+                let revealage = *self.revealage.get(x, y);
+                let [r, g, b, sum] = *self.accum.get(x, y);
+                if revealage >= 1.0 || sum <= 0.0 {
+                    continue;
+                }
+                // 加权平均颜色覆盖(1 - revealage)的比例
+                let color = LinearColor::new(r / sum, g / sum, b / sum, 1.0 - revealage);
+                target.blend(x, y, color, BlendMode::Alpha);
             }
         }
     }
@@ -282,6 +629,15 @@ pub struct FrameBuffer<D> {
     data: Vec<D>,
 }
 
+impl<D> fmt::Debug for FrameBuffer<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameBuffer")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<D: Default + Clone + Copy> FrameBuffer<D> {
     pub fn new(width: i32, height: i32) -> Self {
         Self {
@@ -329,19 +685,251 @@ impl<D: Default + Clone + Copy> FrameBuffer<D> {
     }
 }
 
-impl DrawTarget for FrameBuffer<Color> {
-    fn draw(&mut self, x: i32, y: i32, color: Color) {
+impl<D> FrameBuffer<D> {
+    /// DrawTarget坐标系中(x, y)处的数据序号，y轴朝上，超出范围时返回None
+    fn target_index(&self, x: i32, y: i32) -> Option<usize> {
         if x >= self.width || y >= self.height {
-            return;
+            return None;
         }
         let y = self.height - y - 1;
         let index = (y * self.width + x) as usize;
-        if index < self.data.len() {
+        (index < self.data.len()).then_some(index)
+    }
+}
+
+impl DrawTarget for FrameBuffer<Color> {
+    fn draw(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(index) = self.target_index(x, y) {
+            self.data[index] = color;
+        }
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> Color {
+        self.target_index(x, y)
+            .map_or_else(Color::default, |index| self.data[index])
+    }
+
+    fn get_size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+}
+
+impl DrawTarget for HdrBuffer {
+    fn draw(&mut self, x: i32, y: i32, color: Color) {
+        self.draw_linear(x, y, color.into());
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> Color {
+        self.pixel_linear(x, y).into()
+    }
+
+    fn draw_linear(&mut self, x: i32, y: i32, color: LinearColor) {
+        if let Some(index) = self.target_index(x, y) {
             self.data[index] = color;
         }
     }
 
+    fn pixel_linear(&self, x: i32, y: i32) -> LinearColor {
+        self.target_index(x, y)
+            .map_or_else(LinearColor::default, |index| self.data[index])
+    }
+
     fn get_size(&self) -> (i32, i32) {
         (self.width, self.height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad_triangle(z: f32) -> Triangle2D {
+        Triangle2D {
+            a: Vector2::new([0, 0]),
+            b: Vector2::new([8, 0]),
+            c: Vector2::new([0, 8]),
+            depth: Vector3::new([z; 3]),
+            uv_a: Vector2::new_zero(),
+            uv_b: Vector2::new_zero(),
+            uv_c: Vector2::new_zero(),
+            intensity: Vector3::new([1.0; 3]),
+            color_a: Vector3::new([1.0; 3]),
+            color_b: Vector3::new([1.0; 3]),
+            color_c: Vector3::new([1.0; 3]),
+            light_a: Vector3::new([0.0, 0.0, 1.0]),
+            light_b: Vector3::new([0.0, 0.0, 1.0]),
+            light_c: Vector3::new([0.0, 0.0, 1.0]),
+        }
+    }
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn test_blend() {
+        // 混合在线性空间中计算，结果重新编码为sRGB
+        let dst = LinearColor::from(rgba(0, 0, 200, 255));
+        let blend = |src: Color, mode| Color::from(LinearColor::from(src).blend(dst, mode));
+        let c = blend(rgba(255, 0, 0, 128), BlendMode::Alpha);
+        assert_eq!((c.r, c.g, c.b, c.a), (188, 0, 146, 255));
+        let c = blend(rgba(100, 0, 100, 128), BlendMode::Additive);
+        assert_eq!((c.r, c.b), (72, 210));
+        let c = blend(rgba(128, 0, 0, 128), BlendMode::Premultiplied);
+        assert_eq!((c.r, c.b), (128, 146));
+    }
+
+    #[test]
+    fn test_blend_equations() {
+        // 单个像素上直接检查各混合方式的公式，取值都能精确表示
+        let src = LinearColor::new(0.5, 0.25, 0.0, 0.5);
+        let dst = LinearColor::new(0.25, 0.5, 1.0, 1.0);
+        assert_eq!(
+            src.blend(dst, BlendMode::Alpha),
+            LinearColor::new(0.375, 0.375, 0.5, 1.0)
+        );
+        assert_eq!(
+            src.blend(dst, BlendMode::Additive),
+            LinearColor::new(0.5, 0.625, 1.0, 1.0)
+        );
+        assert_eq!(
+            src.blend(dst, BlendMode::Premultiplied),
+            LinearColor::new(0.625, 0.5, 0.5, 1.0)
+        );
+        // 预乘后再混合与普通混合结果相同
+        assert_eq!(
+            src.scale(src.a).blend(dst, BlendMode::Premultiplied),
+            src.blend(dst, BlendMode::Alpha)
+        );
+        // 叠加到透明的背景上，不透明度取源颜色的
+        let c = src.blend(LinearColor::default(), BlendMode::Additive);
+        assert_eq!(c, LinearColor::new(0.25, 0.125, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_scale() {
+        // 光照强度在线性空间中相乘，一半强度的白色为sRGB的188而不是128
+        let scale = |c: Color, fx| Color::from(LinearColor::from(c).scale(fx));
+        let white = rgba(255, 255, 255, 255);
+        assert_eq!(scale(white, 0.5).r, 188);
+        assert_eq!(scale(white, 0.0).r, 0);
+        assert_eq!(scale(white, 2.0).r, 255);
+        assert_eq!(scale(rgba(128, 0, 0, 7), 1.0), rgba(128, 0, 0, 7));
+    }
+
+    #[test]
+    fn test_transparency() {
+        let mut zbuffer = FrameBuffer::<f32>::new(8, 8);
+        zbuffer.fill(-f32::MAX);
+        let red: fn(Fragment) -> Option<LinearColor> = |_| Some(rgba(255, 0, 0, 128).into());
+        let green: fn(Fragment) -> Option<LinearColor> = |_| Some(rgba(0, 255, 0, 128).into());
+
+        // 由远到近绘制时，近处的颜色占比更大
+        let mut fb = FrameBuffer::<Color>::new(8, 8);
+        fb.draw_triangle_blended(quad_triangle(0.2), &zbuffer, BlendMode::Alpha, red);
+        fb.draw_triangle_blended(quad_triangle(0.8), &zbuffer, BlendMode::Alpha, green);
+        let sorted = fb.pixel(1, 1);
+        assert!(sorted.g > sorted.r);
+        // 三角形之外的像素保持不变
+        assert_eq!(fb.pixel(7, 7).a, 0);
+
+        // OIT与绘制顺序无关，且同样偏向近处的颜色
+        let mut results = Vec::new();
+        for order in [[0.2, 0.8], [0.8, 0.2]] {
+            let mut oit = OitBuffer::new(8, 8);
+            for z in order {
+                let shader = if z < 0.5 { red } else { green };
+                oit.draw_triangle(quad_triangle(z), &zbuffer, shader);
+            }
+            let mut fb = FrameBuffer::<Color>::new(8, 8);
+            oit.resolve(&mut fb);
+            results.push(fb.pixel(1, 1));
+        }
+        let (a, b) = (results[0], results[1]);
+        assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b));
+        assert!(a.g > a.r);
+
+        // 被不透明物体遮挡的半透明像素不绘制
+        zbuffer.fill(1.0);
+        let mut fb = FrameBuffer::<Color>::new(8, 8);
+        fb.draw_triangle_blended(quad_triangle(0.8), &zbuffer, BlendMode::Alpha, red);
+        assert_eq!(fb.pixel(1, 1).a, 0);
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert_eq!(ToneMapping::Clamp.apply(0.5), 0.5);
+        assert_eq!(ToneMapping::Clamp.apply(2.0), 1.0);
+        assert!(close(ToneMapping::Reinhard.apply(3.0), 0.75));
+        assert!(close(ToneMapping::AcesFilmic.apply(1.0), 2.54 / 3.16));
+        // ACES在x约为10时就已饱和，而不是无限趋近1
+        assert_eq!(ToneMapping::AcesFilmic.apply(100.0), 1.0);
+        assert!(close(ToneMapping::Exposure.apply(2f32.ln()), 0.5));
+        // 负值按0处理
+        for mapping in [
+            ToneMapping::Clamp,
+            ToneMapping::Reinhard,
+            ToneMapping::AcesFilmic,
+            ToneMapping::Exposure,
+        ] {
+            assert_eq!(mapping.apply(-1.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_hdr() {
+        for mapping in [
+            ToneMapping::Clamp,
+            ToneMapping::Reinhard,
+            ToneMapping::AcesFilmic,
+            ToneMapping::Exposure,
+        ] {
+            assert_eq!(mapping.apply(0.0), 0.0);
+            assert!(mapping.apply(1e6) <= 1.0 && mapping.apply(1e6) > 0.99);
+            assert!(mapping.apply(0.5) < mapping.apply(2.0));
+        }
+        assert_eq!(ToneMapping::Reinhard.apply(1.0), 0.5);
+
+        // 叠加的亮光在HDR目标中不会截断，色调映射后仍能区分亮度
+        let mut zbuffer = FrameBuffer::<f32>::new(8, 8);
+        zbuffer.fill(-f32::MAX);
+        let light: fn(Fragment) -> Option<LinearColor> = |_| Some(rgba(255, 255, 255, 255).into());
+        let mut hdr = HdrBuffer::new(8, 8);
+        for _ in 0..3 {
+            hdr.draw_triangle_blended(quad_triangle(0.5), &zbuffer, BlendMode::Additive, light);
+        }
+        hdr.draw_triangle_blended(
+            Triangle2D {
+                b: Vector2::new([4, 0]),
+                c: Vector2::new([0, 4]),
+                ..quad_triangle(0.5)
+            },
+            &zbuffer,
+            BlendMode::Additive,
+            light,
+        );
+        assert_eq!(hdr.pixel_linear(1, 1).r, 4.0);
+        assert_eq!(hdr.pixel_linear(5, 1).r, 3.0);
+
+        let mut ldr = FrameBuffer::<Color>::new(8, 8);
+        hdr.tone_map(&mut ldr, ToneMapping::Clamp, 0.0);
+        assert_eq!((ldr.pixel(1, 1).r, ldr.pixel(5, 1).r), (255, 255));
+        hdr.tone_map(&mut ldr, ToneMapping::Reinhard, 0.0);
+        let (bright, dim) = (ldr.pixel(1, 1), ldr.pixel(5, 1));
+        assert!(bright.r > dim.r && bright.r < 255);
+        assert_eq!(bright.a, 255);
+        // 曝光补偿-2档即亮度变为1/4
+        hdr.tone_map(&mut ldr, ToneMapping::Clamp, -2.0);
+        assert_eq!(ldr.pixel(1, 1).r, 255);
+        assert_eq!(ldr.pixel(5, 1).r, srgb::from_linear(0.75));
+        assert_eq!(ldr.pixel(7, 7).a, 0);
+
+        // 着色器输出的超过1的颜色同样原样保存
+        let mut hdr = HdrBuffer::new(8, 8);
+        hdr.draw_trangle_with_zbuffer(quad_triangle(0.5), &mut zbuffer, |_| {
+            Some(LinearColor::new(4.0, 2.0, 1.0, 1.0))
+        });
+        assert_eq!(hdr.pixel_linear(1, 1), LinearColor::new(4.0, 2.0, 1.0, 1.0));
+    }
+}
@@ -1,107 +1,248 @@
-use std::{collections::HashMap, f32::consts::PI, ops::Sub, time::Instant};
+use std::{env, f32::consts::PI, ops::Sub, time::Instant};
 
-use draw_target::{Color, DrawTarget, FrameBuffer, Triangle2D};
+use draw_target::{
+    BlendMode, DrawTarget, Fragment, FrameBuffer, HdrBuffer, LinearColor, OitBuffer, ToneMapping,
+    Triangle2D,
+};
 
 use mat::Matrix;
-use model::{Model, Texture};
+use model::{
+    simplify::SimplifyOptions, AlphaMode, Filter, ModelCache, NormalMode, NormalWeighting, Sampler,
+    TextureCache, Wrap,
+};
 use util::DisplayWindow;
 use vec::{Vector2, Vector3, Vector4};
 
 mod draw_target;
 mod mat;
 mod model;
+mod srgb;
 mod transform;
 mod util;
 mod vec;
 
 fn main() {
-    let obj = Model::load_from_obj("assets/芙宁娜.obj");
-
-    let pic_map = HashMap::from([
-        ("spa_h.png", Texture::load_from("assets/芙宁娜/spa_h.png")),
-        ("体.png", Texture::load_from("assets/芙宁娜/体.png")),
-        ("颜.png", Texture::load_from("assets/芙宁娜/颜.png")),
-        ("髮.png", Texture::load_from("assets/芙宁娜/髮.png")),
-        ("髮2.png", Texture::load_from("assets/芙宁娜/髮2.png")),
-    ]);
-    let texture_map = HashMap::from([
-        ("颜", "颜.png"),
-        ("颜2", "颜.png"),
-        ("二重", "颜.png"),
-        ("睫", "颜.png"),
-        ("口舌", "颜.png"),
-        ("齿", "颜.png"),
-        ("眉", "颜.png"),
-        ("白目", "颜.png"),
-        ("目", "髮.png"),
-        ("星目", "颜.png"),
-        ("髮", "髮.png"),
-        ("髮2", "髮2.png"),
-        ("体", "体.png"),
-        ("服饰", "体.png"),
-        ("体2", "髮.png"),
-        ("蝴蝶结1", "髮.png"),
-        ("服饰2", "髮.png"),
-        ("裤", "髮.png"),
-        ("神之眼", "髮.png"),
-        ("流苏", "髮.png"),
-        ("肌", "髮.png"),
-        ("花边", "体.png"),
-        ("裙", "体.png"),
-        ("裙摆", "体.png"),
-        ("裙1", "体.png"),
-        ("裙2", "体.png"),
-        ("裙3", "体.png"),
-        ("裙饰", "体.png"),
-        ("裙摆+", "体.png"),
-        ("裙1+", "体.png"),
-        ("裙2+", "体.png"),
-        ("髮+", "spa_h.png"),
-    ]);
-
-    // let obj = Model::load_from_obj("assets/可莉.obj");
-    // let pic_map = HashMap::from([
-    //     ("spa_h.png", Texture::load_from("assets/可莉/spa_h.png")),
-    //     ("体.png", Texture::load_from("assets/可莉/体.png")),
-    //     ("颜.png", Texture::load_from("assets/可莉/颜.png")),
-    //     ("髮.png", Texture::load_from("assets/可莉/髮.png")),
-    //     ("肌.png", Texture::load_from("assets/可莉/肌.png")),
-    // ]);
-    // let texture_map = HashMap::from([
-    //     ("颜", "颜.png"),
-    //     ("颜2", "颜.png"),
-    //     ("白目", "颜.png"),
-    //     ("二重", "颜.png"),
-    //     ("目", "体.png"),
-    //     ("睫", "颜.png"),
-    //     ("口舌", "颜.png"),
-    //     ("齿", "颜.png"),
-    //     ("眉", "颜.png"),
-    //     ("星目", "颜.png"),
-    //     ("髮", "髮.png"),
-    //     ("帽", "髮.png"),
-    //     ("饰", "髮.png"),
-    //     ("头饰", "髮.png"),
-    //     ("体", "体.png"),
-    //     ("裙", "体.png"),
-    //     ("裙饰", "髮.png"),
-    //     ("裙饰2", "髮.png"),
-    //     ("裤", "体.png"),
-    //     ("神之眼框", "体.png"),
-    //     ("神之眼AL", "体.png"),
-    //     ("肌", "体.png"),
-    //     ("肌2", "肌.png"),
-    //     ("饰2", "髮.png"),
-    //     ("帽饰alpha", "髮.png"),
-    //     ("裙边alpha", "髮.png"),
-    //     ("髮+", "spa_h.png"),
-    // ]);
+    // 命令行参数为要绘制的部件名，不指定时绘制整个模型
+    // 加上--oit时用顺序无关透明绘制半透明面片，否则按深度排序后绘制
+    let (flags, parts): (Vec<_>, Vec<_>) = env::args().skip(1).partition(|a| a.starts_with("--"));
+    let has_flag = |name: &str| flags.iter().any(|f| f == name);
+    let oit = has_flag("--oit");
+    // 不用OIT时，加上--blend=additive或--blend=premultiplied可以改变半透明面片的混合方式
+    let blend_mode = match flags.iter().find_map(|f| f.strip_prefix("--blend=")) {
+        None | Some("alpha") => BlendMode::Alpha,
+        Some("additive") => BlendMode::Additive,
+        Some("premultiplied") => BlendMode::Premultiplied,
+        Some(mode) => {
+            eprintln!("unknown blend mode {mode}, using alpha");
+            BlendMode::Alpha
+        }
+    };
+
+    // 解析后的模型缓存在临时目录中，再次启动时直接读取，加上--clear-cache时先清空缓存
+    let cache = ModelCache::new(env::temp_dir().join("tinyrenderer-rs"));
+    if has_flag("--clear-cache") {
+        if let Err(e) = cache.clear() {
+            eprintln!("failed to clear model cache: {e}");
+        }
+    }
+    let mut obj = match cache.load("assets/芙宁娜.obj") {
+        Ok((obj, cache_error)) => {
+            // 写缓存失败不影响绘制，下次启动时重新解析
+            if let Some(e) = cache_error {
+                eprintln!("failed to write model cache: {e}");
+            }
+            obj
+        }
+        Err(e) => {
+            eprintln!("failed to load model: {e}");
+            return;
+        }
+    };
+    // 模型没有法向量时按内角加权生成平滑法向量，加上--area时改按面积加权，
+    // 加上--flat时使用面片法向量，这两种情况都会替换模型自带的法向量
+    let weighting = if has_flag("--area") {
+        NormalWeighting::Area
+    } else {
+        NormalWeighting::Angle
+    };
+    let normal_mode = if has_flag("--flat") {
+        NormalMode::Flat
+    } else {
+        NormalMode::Smooth {
+            weighting,
+            crease_angle: None,
+        }
+    };
+    if !obj.has_normals() || has_flag("--flat") || has_flag("--area") {
+        obj.generate_normals(normal_mode);
+    }
+    // 加上--save=<文件>时把加载后的模型另存为obj或二进制格式trmb
+    if let Some(path) = flags.iter().find_map(|f| f.strip_prefix("--save=")) {
+        if let Err(e) = obj.save(path) {
+            eprintln!("failed to save model to {path}: {e}");
+        }
+    }
+
+    // let obj = cache.load("assets/可莉.obj").unwrap();
+
+    // 贴图按路径在首次使用时加载并生成mipmap，多个材质共用或内容相同的贴图只加载一次，
+    // 模型内嵌的贴图直接放入缓存
+    let mut textures = TextureCache::new();
+    for (path, texture) in obj.take_embedded_textures() {
+        textures.insert(path, texture);
+    }
+    // 每个材质的漫反射贴图，加载失败时按没有贴图绘制
+    let material_textures = obj
+        .materials()
+        .iter()
+        .map(|m| {
+            let path = m.diffuse_map.as_ref()?;
+            textures
+                .get(path)
+                .map_err(|e| eprintln!("failed to load texture {}: {e}", path.display()))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    // 每个材质的透明度贴图，存放的是数据而不是颜色，不做sRGB解码
+    let alpha_textures = obj
+        .materials()
+        .iter()
+        .map(|m| {
+            let path = m.alpha_map.as_ref()?;
+            textures
+                .get_linear(path)
+                .map_err(|e| eprintln!("failed to load texture {}: {e}", path.display()))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    // 每个材质的法线贴图，同样存放的是数据
+    let normal_textures = obj
+        .materials()
+        .iter()
+        .map(|m| {
+            let path = m.bump_map.as_ref()?;
+            textures
+                .get_linear(path)
+                .map_err(|e| eprintln!("failed to load texture {}: {e}", path.display()))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    // 内嵌但没有材质引用的贴图不再需要
+    textures.evict_unused();
+    if !textures.is_empty() {
+        println!(
+            "textures: {}, {:.1} MiB",
+            textures.len(),
+            textures.memory_usage() as f64 / (1024.0 * 1024.0)
+        );
+    }
+    // 头发、裙边等材质的贴图带有透明像素，未指定透明模式时按镂空处理
+    for (id, texture) in material_textures.iter().enumerate() {
+        let m = obj.material_mut(id);
+        if m.alpha_mode == AlphaMode::Opaque
+            && texture.as_ref().is_some_and(|t| t.base().has_alpha())
+        {
+            m.alpha_mode = AlphaMode::Cutout;
+        }
+    }
+    // 默认三线性过滤，超出[0, 1]的UV按OBJ的约定平铺重复，斜看的裙摆等表面用各向异性过滤保持清晰
+    // 加上--filter=nearest|bilinear|trilinear、--wrap=repeat|mirror|clamp|border可以对比其他采样方式
+    let filter = match flags.iter().find_map(|f| f.strip_prefix("--filter=")) {
+        None | Some("trilinear") => Filter::Trilinear,
+        Some("nearest") => Filter::Nearest,
+        Some("bilinear") => Filter::Bilinear,
+        Some(filter) => {
+            eprintln!("unknown filter {filter}, using trilinear");
+            Filter::Trilinear
+        }
+    };
+    let wrap = match flags.iter().find_map(|f| f.strip_prefix("--wrap=")) {
+        None | Some("repeat") => Wrap::Repeat,
+        Some("mirror") => Wrap::MirroredRepeat,
+        Some("clamp") => Wrap::ClampToEdge,
+        Some("border") => Wrap::ClampToBorder,
+        Some(wrap) => {
+            eprintln!("unknown wrap mode {wrap}, using repeat");
+            Wrap::Repeat
+        }
+    };
+    let sampler = Sampler {
+        max_anisotropy: 16,
+        ..Sampler::new(filter).with_wrap(wrap)
+    };
+    // 叠加混合的半透明面片会超过1，默认用ACES曲线压缩高光
+    // 加上--tone=clamp|reinhard|aces|exposure可以换用其他算子，--exposure=<ev>调整曝光补偿
+    let tone_mapping = match flags.iter().find_map(|f| f.strip_prefix("--tone=")) {
+        None | Some("aces") => ToneMapping::AcesFilmic,
+        Some("clamp") => ToneMapping::Clamp,
+        Some("reinhard") => ToneMapping::Reinhard,
+        Some("exposure") => ToneMapping::Exposure,
+        Some(tone) => {
+            eprintln!("unknown tone mapping {tone}, using aces");
+            ToneMapping::AcesFilmic
+        }
+    };
+    let exposure = match flags.iter().find_map(|f| f.strip_prefix("--exposure=")) {
+        None => 0.0,
+        Some(ev) => ev.parse::<f32>().unwrap_or_else(|_| {
+            eprintln!("invalid exposure {ev}, using 0");
+            0.0
+        }),
+    };
+
+    // 各模型的尺寸和原点不同，统一缩放到原点处的单位立方体内
+    obj.normalize_to_unit_cube();
+    // 有材质带法线贴图(map_Bump)时生成切线，法线贴图中的法向量在切线空间中
+    if obj.materials().iter().any(|m| m.bump_map.is_some()) {
+        obj.generate_tangents();
+    }
+    // 指定的部件不存在时列出模型中所有的部件名
+    if parts
+        .iter()
+        .any(|p| obj.sub_meshes_named(p).next().is_none())
+    {
+        let mut names = obj
+            .sub_meshes()
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        eprintln!("unknown part name, available parts: {}", names.join(", "));
+    }
+    // 依次减半面片数生成LOD，模型在屏幕上较小时绘制简化后的模型
+    let lods = obj.generate_lods(&[2, 4, 8].map(|d| SimplifyOptions {
+        target_faces: obj.faces_count() / d,
+        max_error: f32::INFINITY,
+    }));
+    let sphere = obj.bounding_sphere();
+    // 每一级的模型、索引顶点缓冲和材质批次，第0级为原模型
+    let levels = std::iter::once(&obj)
+        .chain(lods.levels.iter().map(|lod| &lod.model))
+        .map(|model| {
+            let batches = if parts.is_empty() {
+                model.material_batches()
+            } else {
+                model.material_batches_of(model.faces_where(|s| parts.contains(&s.name)))
+            };
+            // 合并相同的面片顶点，每帧只需变换去重后的顶点
+            (model, model.to_indexed(), batches)
+        })
+        .collect::<Vec<_>>();
+    let has_uvs = obj.has_uvs();
 
     let (w, h) = (1000, 1000);
     let mut window = DisplayWindow::new(w, h);
+    let mut oit_buffer = OitBuffer::new(w, h);
+    // 先渲染到浮点的HDR目标，光照叠加后不会溢出，最后经色调映射输出到窗口
+    let mut hdr = HdrBuffer::new(w, h);
 
-    let mut eye = Vector3::new([0.0, 0.0, 1.0]);
+    // 相机后退到恰好能看到整个模型的位置
+    let fov = PI * 0.5;
     let mut look_at = Vector3::new([0.0, 0.0, -1.0]);
+    let mut eye = sphere.map_or(Vector3::new([0.0, 0.0, 1.0]), |sphere| {
+        sphere.frame(fov, w as f32 / h as f32, look_at)
+    });
     let up = Vector3::new([0.0, 1.0, 0.0]);
 
     let mut fps = 0.0;
@@ -116,65 +257,154 @@ fn main() {
             last_time = now;
         }
         let r = (angle as f32 / 1000.0) * 2.0 * PI;
-        window.fb.clear();
+        hdr.clear();
         let light_dir = Vector3::new([0.0, 0.0, -1.0]);
+        let to_light = Vector3::new_zero() - light_dir;
         let mut zbuffer = FrameBuffer::<f32>::new(w, h);
         zbuffer.fill(-f32::MAX);
+        let model_rotate = transform::rotate(Vector3::new([0.0, 1.0, 0.0]), r);
         let mvp = // to
             transform::scale(w as f32, h as f32, 1.0) // Viewport视口变换到屏幕坐标系
                 * transform::scale(0.5, 0.5, 0.5) * transform::translate(Vector3::new([1.0, 1.0, 1.0])) // Scale规范化坐标系
-                * transform::persp_by_fov(PI * 0.5, w as f32 / h as f32, -0.1, 50.0)  // Project投影变换到规范化坐标系
+                * transform::persp_by_fov(fov, w as f32 / h as f32, -0.1, 50.0)  // Project投影变换到规范化坐标系
                 * transform::camera(eye, look_at, up) // View相机变换到相机坐标系
-                * transform::translate(Vector3::new([0.0, 0.0, -1.0])) * transform::rotate(Vector3::new([0.0, 1.0, 0.0]), r); // Model模型变换到世界坐标系
-        for i in 0..obj.faces_count() {
-            // 获取[(坐标序号，UV坐标序号，法向量序号)]
-            let (face, mtl) = obj.get_face(i);
-            let pic = &pic_map[texture_map[obj.get_mtl(mtl)]];
-            // 分别对三个顶点做变换
-            let t = (0..3)
-                .map(|j| {
-                    // 模型坐标系中得到模型坐标
-                    let wc = obj.get_vertex(face[j].0).to_homo_coord();
-
-                    // 法向量计算
-                    let norm_src = Vector3::from_homo_coord(
-                        transform::rotate(Vector3::new([0.0, 1.0, 0.0]), r)
-                            * obj.get_normal(face[j].2).to_homo_coord(),
-                    );
+                * model_rotate; // Model模型变换到世界坐标系
+
+        // 按包围球在屏幕上的大小选择LOD，误差不超过1像素
+        let level = sphere
+            .and_then(|sphere| lods.select(sphere.projected_size(eye, fov, h as f32), 1.0))
+            .map_or(0, |i| i + 1);
+        let (model, mesh, batches) = &levels[level];
+
+        // 顶点阶段：每个去重后的顶点只变换一次
+        let transformed = mesh
+            .vertices
+            .iter()
+            .map(|v| {
+                // 法向量、切线、副切线随模型旋转
+                let rotate =
+                    |d: Vector3<f32>| Vector3::from_homo_coord(model_rotate * d.to_homo_coord());
+                let (n, t, b) = (rotate(v.normal), rotate(v.tangent), rotate(v.bitangent));
+
+                // 光源方向变换到切线空间，在片元中与法线贴图的法向量计算光照
+                // 没有切线时只有z分量，即顶点法向量与光源方向的点积
+                let light = Vector3::new([t.dot(to_light), b.dot(to_light), n.dot(to_light)]);
+
+                // 齐次坐标系映射到笛卡尔坐标系
+                let wc = Vector3::from_homo_coord(mvp * v.position.to_homo_coord()); // 顶点各种变换
+
+                // 屏幕坐标，屏幕深度，uv坐标, 切线空间的光源方向, 顶点颜色
+                (
+                    Vector2::new([wc.x() as i32, wc.y() as i32]),
+                    wc.z(),
+                    v.uv,
+                    light,
+                    v.color,
+                )
+            })
+            .collect::<Vec<_>>();
+        // 三角形的三个顶点直接取变换结果
+        let triangle = |i: usize| {
+            let t = mesh.triangle(i).map(|v| transformed[v]);
+            Triangle2D {
+                a: t[0].0,
+                b: t[1].0,
+                c: t[2].0,
+                depth: Vector3::new([t[0].1, t[1].1, t[2].1]),
+
+                uv_a: t[0].2,
+                uv_b: t[1].2,
+                uv_c: t[2].2,
 
-                    // 光照计算
-                    let intensity = (1.0 - norm_src.dot(light_dir)) * 0.5;
-
-                    // 齐次坐标系映射到笛卡尔坐标系
-                    let wc = Vector3::from_homo_coord(mvp * wc); // 顶点各种变换
-
-                    // 屏幕坐标，屏幕深度，uv坐标, 光照强度
-                    (
-                        Vector2::new([wc.x() as i32, wc.y() as i32]),
-                        wc.z(),
-                        obj.get_uv(face[j].1),
-                        intensity,
-                    )
-                })
-                .collect::<Vec<_>>();
-
-            window.fb.draw_trangle_with_zbuffer(
-                Triangle2D {
-                    a: t[0].0,
-                    b: t[1].0,
-                    c: t[2].0,
-                    depth: Vector3::new([t[0].1, t[1].1, t[2].1]),
-
-                    uv_a: t[0].2,
-                    uv_b: t[1].2,
-                    uv_c: t[2].2,
-
-                    intensity: Vector3::new([t[0].3, t[1].3, t[2].3]),
-                },
-                &mut zbuffer,
-                |uv| pic.get_color(uv),
-            );
+                // 光照在着色函数中计算
+                intensity: Vector3::new([1.0; 3]),
+
+                color_a: t[0].4,
+                color_b: t[1].4,
+                color_c: t[2].4,
+
+                light_a: t[0].3,
+                light_b: t[1].3,
+                light_c: t[2].3,
+            }
+        };
+        // 同一批面片使用同一材质，贴图只需查找一次
+        let shaders = batches
+            .iter()
+            .map(|batch| {
+                let material = batch.material.map(|m| model.get_material(m));
+                let pic = batch.material.and_then(|m| material_textures[m].as_deref());
+                let (alpha_mode, alpha_cutoff) =
+                    material.map_or((AlphaMode::Opaque, 0.5), |m| (m.alpha_mode, m.alpha_cutoff));
+                let dissolve = material.map_or(1.0, |m| m.dissolve);
+                // 透明度贴图带透明通道时取透明度，否则是灰度图，取其亮度
+                let alpha_map = batch
+                    .material
+                    .and_then(|m| alpha_textures[m].as_deref())
+                    .map(|t| (t, t.base().has_alpha()));
+                let normal_map = batch.material.and_then(|m| normal_textures[m].as_deref());
+                // 没有贴图或没有UV时，用顶点颜色代替贴图
+                let use_colors = obj.has_vertex_colors() && (pic.is_none() || !has_uvs);
+                move |fragment: Fragment| {
+                    let mut color = match pic {
+                        // 顶点颜色与贴图一样按sRGB存放
+                        _ if use_colors => LinearColor::from_srgb(fragment.color),
+                        Some(pic) => {
+                            sampler.sample(pic, fragment.uv, fragment.duv_dx, fragment.duv_dy)
+                        }
+                        None => LinearColor::new(1.0, 1.0, 1.0, 1.0),
+                    };
+                    color.a *= dissolve;
+                    if let Some((map, has_alpha)) = alpha_map {
+                        let c = sampler.sample(map, fragment.uv, fragment.duv_dx, fragment.duv_dy);
+                        color.a *= if has_alpha { c.a } else { c.r };
+                    }
+                    // 法线贴图的颜色由[0, 1]映射回[-1, 1]，没有时使用顶点法向量(0, 0, 1)
+                    let normal = normal_map.map_or(Vector3::new([0.0, 0.0, 1.0]), |map| {
+                        let c = sampler.sample(map, fragment.uv, fragment.duv_dx, fragment.duv_dy);
+                        Vector3::new([c.r, c.g, c.b].map(|x| x * 2.0 - 1.0)).normalize()
+                    });
+                    let color = color.scale((1.0 + normal.dot(fragment.light)) * 0.5);
+                    match alpha_mode {
+                        // 预乘混合要求着色结果已乘上不透明度
+                        AlphaMode::Blend if blend_mode == BlendMode::Premultiplied => {
+                            Some(color.scale(color.a))
+                        }
+                        AlphaMode::Opaque | AlphaMode::Blend => Some(color),
+                        AlphaMode::Cutout => color.alpha_test(alpha_cutoff),
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // 先绘制不透明的面片，半透明面片留到最后与其混合
+        let mut transparent = Vec::new();
+        for (batch, shader) in batches.iter().zip(&shaders) {
+            let blend = batch
+                .material
+                .is_some_and(|m| model.get_material(m).alpha_mode == AlphaMode::Blend);
+            for &i in &batch.faces {
+                if blend {
+                    transparent.push((triangle(i), shader));
+                } else {
+                    hdr.draw_trangle_with_zbuffer(triangle(i), &mut zbuffer, shader);
+                }
+            }
+        }
+        if oit {
+            oit_buffer.clear();
+            for (t, shader) in transparent {
+                oit_buffer.draw_triangle(t, &zbuffer, shader);
+            }
+            oit_buffer.resolve(&mut hdr);
+        } else {
+            // 由远到近逐个三角形混合
+            transparent.sort_by(|a, b| a.0.mean_depth().total_cmp(&b.0.mean_depth()));
+            for (t, shader) in transparent {
+                hdr.draw_triangle_blended(t, &zbuffer, blend_mode, shader);
+            }
         }
+        hdr.tone_map(&mut window.fb, tone_mapping, exposure);
         let e = window.update();
         {
             use util::Event::*;
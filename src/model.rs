@@ -1,17 +1,51 @@
-use std::{fs::File, io::Read};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use embedded_graphics::{
     geometry::Dimensions,
     pixelcolor::{Rgb888, RgbColor},
 };
-use image::{GenericImageView, ImageBuffer};
+use image::{DynamicImage, GenericImageView, ImageBuffer};
 use tinytga::Tga;
 
 use crate::{
     draw_target::{Color, FrameBuffer},
-    vec::{Vector2, Vector3},
+    vec::{Vector2, Vector3, Vector4},
 };
 
+mod binary;
+mod bounds;
+mod cache;
+pub mod gltf;
+mod indexed;
+mod mtl;
+mod normals;
+pub mod obj;
+pub mod ply;
+pub mod pmx;
+mod sampler;
+pub mod simplify;
+pub mod stl;
+mod tangents;
+mod texture_cache;
+mod triangulate;
+
+pub use cache::ModelCache;
+pub use mtl::{AlphaMode, Material};
+pub use normals::{NormalMode, NormalWeighting};
+pub use sampler::{Filter, MipChain, Sampler, Wrap};
+pub use texture_cache::TextureCache;
+
+/// 面片上的一个顶点：(三维坐标序号, UV坐标序号, 法向量序号)，缺省的UV或法向量为None
+pub type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+#[derive(Debug, Default)]
 pub struct Model {
     /// 三维顶点坐标列表
     vertexs: Vec<Vector3<f32>>,
@@ -19,107 +53,240 @@ pub struct Model {
     normals: Vec<Vector3<f32>>,
     /// 纹理UV坐标列表
     texture_vertexs: Vec<Vector2<f32>>,
-    /// 面片解析[[(三维坐标序号, UV坐标序号, 法向量序号);3];n]
-    faces: Vec<(Vec<(usize, usize, usize)>, isize)>,
-    /// mtl
-    mtls: Vec<String>,
+    /// 顶点颜色，与顶点坐标一一对应，为空表示没有顶点颜色
+    colors: Vec<Vector3<f32>>,
+    /// 三角化后的面片[[(三维坐标序号, UV坐标序号, 法向量序号);3];n]及其材质id
+    faces: Vec<([FaceVertex; 3], Option<usize>)>,
+    /// 面片所属的平滑组，Some(0)表示关闭平滑，None表示未指定
+    smoothing_groups: Vec<Option<u32>>,
+    /// 每个面片顶点的切线，按面片序号*3+角序号存放，w为手性
+    tangents: Vec<Vector4<f32>>,
+    /// 材质表，同名材质只出现一次，材质id即其在表中的序号
+    materials: Vec<Material>,
+    /// 材质名到材质id的映射
+    material_ids: HashMap<String, usize>,
+    /// 模型文件内嵌的已解码贴图，键为材质中引用的虚拟路径
+    embedded_textures: HashMap<PathBuf, Texture>,
+    /// 命名的部件，按起始面片排序
+    sub_meshes: Vec<SubMesh>,
+    /// 加载时读取的其他文件（如mtllib），用于判断缓存是否过期
+    dependencies: Vec<PathBuf>,
+}
+
+/// 部件的类别，对应OBJ中的o与g
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubMeshKind {
+    Object,
+    Group,
+}
+
+/// 模型中的一个命名部件，由一段连续的面片组成
+/// 同名部件可能出现多次，一个面片也可能同时属于一个对象和多个组
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubMesh {
+    pub name: String,
+    pub kind: SubMeshKind,
+    /// 面片序号范围
+    pub faces: Range<usize>,
+}
+
+/// 使用同一材质的一批面片，用于按材质批量绘制
+#[derive(Debug, Clone)]
+pub struct MaterialBatch {
+    /// 材质id，None表示未指定材质
+    pub material: Option<usize>,
+    /// 面片序号
+    pub faces: Vec<usize>,
 }
 
 impl Model {
-    pub fn load_from_obj(filename: &str) -> Self {
-        let mut vertexs = Vec::new();
-        let mut texture_vertexs = Vec::new();
-        let mut faces = Vec::new();
-        let mut normals = Vec::new();
-        let mut mtls = Vec::new();
-        let mut s = String::new();
-
-        File::open(filename)
-            .unwrap()
-            .read_to_string(&mut s)
-            .unwrap();
-
-        let mut current_mtl_id = -1;
-        s.split_terminator('\n').for_each(|line| {
-            let mut whitespace_split_res = line.split_whitespace();
-            let first_flag = whitespace_split_res.next();
-            match first_flag {
-                Some("usemtl") => {
-                    let mtl = whitespace_split_res.next().unwrap().to_string();
-                    current_mtl_id += 1;
-                    mtls.push(mtl);
-                }
-                // 顶点和法向量解析
-                Some("v") | Some("vn") => {
-                    let x = whitespace_split_res.next().unwrap().parse::<f32>().unwrap();
-                    let y = whitespace_split_res.next().unwrap().parse::<f32>().unwrap();
-                    let z = whitespace_split_res.next().unwrap().parse::<f32>().unwrap();
-                    let tuple = Vector3::new([x, y, z]);
-                    match first_flag {
-                        Some("v") => vertexs.push(tuple),
-                        Some("vn") => normals.push(tuple),
-                        _ => unreachable!(),
-                    }
-                }
-                // uv坐标解析
-                Some("vt") => {
-                    let x = whitespace_split_res.next().unwrap().parse::<f32>().unwrap();
-                    let y = whitespace_split_res.next().unwrap().parse::<f32>().unwrap();
-                    let tuple = Vector2::new([x, y]);
-                    texture_vertexs.push(tuple);
-                }
-                // 面片解析
-                Some("f") => {
-                    let mut face = Vec::new();
-                    for s in whitespace_split_res {
-                        let mut iter = s.split('/');
-                        let vi = iter.next().unwrap().parse::<usize>().unwrap();
-                        let uvi = iter.next().unwrap().parse::<usize>().unwrap();
-                        let vni = iter.next().unwrap().parse::<usize>().unwrap();
-                        face.push((vi - 1, uvi - 1, vni - 1));
-                    }
-                    faces.push((face, current_mtl_id));
-                }
-                _ => {}
-            }
-        });
-        Self {
-            vertexs,
-            faces,
-            texture_vertexs,
-            normals,
-            mtls,
+    /// 按扩展名选择格式加载模型，支持obj、pmx、gltf、glb、stl、ply以及二进制格式trmb
+    pub fn load(filename: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let filename = filename.as_ref();
+        let ext = filename
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        Ok(match ext.as_deref() {
+            Some("pmx") => Self::load_from_pmx(filename)?,
+            Some("gltf" | "glb") => Self::load_from_gltf(filename)?,
+            Some("stl") => Self::load_from_stl(filename)?,
+            Some("ply") => Self::load_from_ply(filename)?,
+            Some("trmb") => Self::load_binary(filename)?,
+            _ => Self::load_from_obj(filename)?,
+        })
+    }
+
+    /// 按扩展名选择格式保存模型，支持obj以及二进制格式trmb
+    pub fn save(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let filename = filename.as_ref();
+        let ext = filename
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match ext.as_deref() {
+            Some("obj") => self.save_obj(filename),
+            Some("trmb") => self.save_binary(filename),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unsupported model format for saving",
+            )),
         }
     }
 
     /// 获取顶点坐标
+    #[cfg(test)]
     pub fn get_vertex(&self, index: usize) -> Vector3<f32> {
         self.vertexs[index]
     }
 
+    #[cfg(test)]
     pub fn get_normal(&self, index: usize) -> Vector3<f32> {
         self.normals[index]
     }
 
     // 获取uv坐标
+    #[cfg(test)]
     pub fn get_uv(&self, index: usize) -> Vector2<f32> {
         self.texture_vertexs[index]
     }
 
+    /// 获取顶点颜色，取值范围0~1
+    pub fn get_vertex_color(&self, index: usize) -> Option<Vector3<f32>> {
+        self.colors.get(index).copied()
+    }
+
+    /// 是否带有顶点颜色
+    pub fn has_vertex_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    /// 是否所有面片顶点都有UV坐标
+    pub fn has_uvs(&self) -> bool {
+        self.faces
+            .iter()
+            .all(|(face, _)| face.iter().all(|v| v.1.is_some()))
+    }
+
     // 平面顶点列表，顶点由(坐标序号，UV坐标序号，法向量序号, 材质id)所表示
-    pub fn get_face(&self, index: usize) -> ([(usize, usize, usize); 3], isize) {
-        (
-            [
-                self.faces[index].0[0],
-                self.faces[index].0[1],
-                self.faces[index].0[2],
-            ],
-            self.faces[index].1,
-        )
+    #[cfg(test)]
+    pub fn get_face(&self, index: usize) -> ([FaceVertex; 3], Option<usize>) {
+        self.faces[index]
+    }
+
+    /// 由三个顶点坐标计算面片的单位法向量
+    pub fn get_face_normal(&self, index: usize) -> Vector3<f32> {
+        let [a, b, c] = self.faces[index].0.map(|v| self.vertexs[v.0]);
+        (b - a).cross(c - a).normalize()
+    }
+
+    /// 获取面片所属的平滑组
+    pub fn smoothing_group(&self, index: usize) -> Option<u32> {
+        self.smoothing_groups.get(index).copied().flatten()
+    }
+
+    pub fn get_material(&self, index: usize) -> &Material {
+        &self.materials[index]
+    }
+
+    /// 按名称查找材质id
+    pub fn find_material(&self, name: &str) -> Option<usize> {
+        self.material_ids.get(name).copied()
+    }
+
+    /// 材质表
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
     }
 
-    pub fn get_mtl(&self, index: isize) -> &str {
-        &self.mtls[index as usize]
+    /// 修改材质属性，材质名不应修改
+    pub fn material_mut(&mut self, index: usize) -> &mut Material {
+        &mut self.materials[index]
+    }
+
+    /// 将面片按材质分组，各组按材质首次出现的顺序排列，组内保持面片原有顺序
+    pub fn material_batches(&self) -> Vec<MaterialBatch> {
+        self.material_batches_of(0..self.faces.len())
+    }
+
+    /// 只对给定的面片按材质分组，用于绘制部分部件
+    pub fn material_batches_of(
+        &self,
+        faces: impl IntoIterator<Item = usize>,
+    ) -> Vec<MaterialBatch> {
+        let mut batches = Vec::<MaterialBatch>::new();
+        let mut batch_ids = HashMap::new();
+        for i in faces {
+            let material = &self.faces[i].1;
+            let id = *batch_ids.entry(*material).or_insert_with(|| {
+                batches.push(MaterialBatch {
+                    material: *material,
+                    faces: Vec::new(),
+                });
+                batches.len() - 1
+            });
+            batches[id].faces.push(i);
+        }
+        batches
+    }
+
+    /// 添加材质，已存在同名材质时覆盖其属性并沿用原来的id
+    fn insert_material(&mut self, material: Material) -> usize {
+        match self.material_ids.get(&material.name) {
+            Some(&id) => {
+                self.materials[id] = material;
+                id
+            }
+            None => {
+                self.material_ids
+                    .insert(material.name.clone(), self.materials.len());
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        }
+    }
+
+    /// 在名称后追加`#n`，得到材质表中尚未使用的名称
+    fn unique_material_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.find_material(&unique).is_some() {
+            n += 1;
+            unique = format!("{name}#{n}");
+        }
+        unique
+    }
+
+    /// 按名称获取材质id，不存在时以默认属性新建
+    fn material_id_or_insert(&mut self, name: &str) -> usize {
+        match self.find_material(name) {
+            Some(id) => id,
+            None => self.insert_material(Material::new(name)),
+        }
+    }
+
+    /// 所有命名部件
+    pub fn sub_meshes(&self) -> &[SubMesh] {
+        &self.sub_meshes
+    }
+
+    /// 按名称查找部件
+    pub fn sub_meshes_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a SubMesh> {
+        self.sub_meshes.iter().filter(move |s| s.name == name)
+    }
+
+    /// 属于任一满足条件的部件的面片序号，升序且不重复
+    pub fn faces_where(&self, pred: impl Fn(&SubMesh) -> bool) -> Vec<usize> {
+        let mut selected = vec![false; self.faces.len()];
+        for s in self.sub_meshes.iter().filter(|s| pred(s)) {
+            selected[s.faces.clone()].fill(true);
+        }
+        (0..self.faces.len()).filter(|&i| selected[i]).collect()
+    }
+
+    /// 取出模型文件内嵌的贴图，材质引用的其余贴图需要按路径从文件加载
+    pub fn take_embedded_textures(&mut self) -> HashMap<PathBuf, Texture> {
+        std::mem::take(&mut self.embedded_textures)
     }
 
     pub fn vertexs_count(&self) -> usize {
@@ -134,13 +301,6 @@ impl Model {
 pub type Texture = FrameBuffer<Color>;
 
 impl Texture {
-    pub fn get_color(&self, uv: Vector2<f32>) -> Color {
-        let (u, v) = (uv.x(), uv.y());
-        let x = (u * self.get_width() as f32) as i32;
-        let y = ((1.0 - v) * self.get_height() as f32) as i32;
-        *self.get(x, y)
-    }
-
     pub fn load_from_tga(filename: &str) -> Self {
         let mut buf = Vec::new();
         File::open(filename).unwrap().read_to_end(&mut buf).unwrap();
@@ -151,20 +311,25 @@ impl Texture {
         for p in tga.pixels() {
             let (x, y) = p.0.into();
             let (r, g, b) = (p.1.r(), p.1.g(), p.1.b());
-            fb.set(x, y, Color { r, g, b });
+            fb.set(x, y, Color { r, g, b, a: 255 });
         }
         fb
     }
 
-    pub fn load_from(filename: &str) -> Self {
-        let img = image::io::Reader::open(filename).expect(&format!("failed open file {}", filename)).decode().unwrap();
+    /// 从已解码的图片构造，没有透明通道的图片完全不透明
+    pub fn from_image(img: &DynamicImage) -> Self {
         let (w, h) = (img.width(), img.height());
         let mut fb = Self::new(w as i32, h as i32);
 
         for (x, y, c) in img.pixels() {
-            let (r, g, b) = (c.0[0], c.0[1], c.0[2]);
-            fb.set(x as i32, y as i32, Color { r, g, b });
+            let [r, g, b, a] = c.0;
+            fb.set(x as i32, y as i32, Color { r, g, b, a });
         }
         fb
     }
+
+    /// 是否有半透明或透明的像素
+    pub fn has_alpha(&self) -> bool {
+        self.get_data().iter().any(|c| c.a < 255)
+    }
 }
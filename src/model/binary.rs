@@ -0,0 +1,365 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::vec::Vector;
+
+use super::{AlphaMode, FaceVertex, Material, Model, SubMesh, SubMeshKind};
+
+/// 二进制模型文件的文件头
+const MAGIC: &[u8; 4] = b"TRMB";
+/// 格式版本，布局变化时递增，旧版本的文件直接拒绝读取
+const VERSION: u32 = 4;
+/// 表示None的序号，有效的序号必须小于它
+const NONE: u32 = u32::MAX;
+
+impl Model {
+    /// 保存为紧凑的二进制格式，可以原样还原坐标、UV、法向量、顶点颜色、面片、平滑组、材质和部件
+    /// 内嵌贴图与切线不会保存
+    pub fn save_binary(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(filename)?);
+        self.write_binary(&mut w)?;
+        w.flush()
+    }
+
+    /// 整个文件读入内存后再解析，比经由BufReader逐个字段读取更快
+    pub fn load_binary(filename: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_binary(&mut fs::read(filename)?.as_slice())
+    }
+
+    /// 所有数值按小端序写出，序号或个数超出u32范围时返回`InvalidData`错误
+    pub fn write_binary(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut w = Writer(w);
+        w.0.write_all(MAGIC)?;
+        w.u32(VERSION)?;
+        w.vectors(&self.vertexs)?;
+        w.vectors(&self.normals)?;
+        w.vectors(&self.texture_vertexs)?;
+        w.vectors(&self.colors)?;
+
+        w.len(self.faces.len())?;
+        for (i, (face, material)) in self.faces.iter().enumerate() {
+            for (v, uv, n) in face {
+                w.usize(*v)?;
+                w.index(*uv)?;
+                w.index(*n)?;
+            }
+            w.index(*material)?;
+            // 平滑组可以取任意u32值，用单独的标记区分None
+            match self.smoothing_group(i) {
+                Some(group) => {
+                    w.u8(1)?;
+                    w.u32(group)?;
+                }
+                None => w.u8(0)?,
+            }
+        }
+
+        w.len(self.materials.len())?;
+        for m in &self.materials {
+            w.str(&m.name)?;
+            for c in [m.ambient, m.diffuse, m.specular, m.emissive] {
+                w.vector(c)?;
+            }
+            for f in [m.shininess, m.dissolve, m.metallic, m.roughness] {
+                w.f32(f)?;
+            }
+            w.u32(m.illum)?;
+            w.u32(match m.alpha_mode {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Cutout => 1,
+                AlphaMode::Blend => 2,
+            })?;
+            w.f32(m.alpha_cutoff)?;
+            for map in [&m.diffuse_map, &m.bump_map, &m.specular_map, &m.alpha_map] {
+                w.str(
+                    &map.as_ref()
+                        .map_or(String::new(), |p| p.to_string_lossy().into()),
+                )?;
+            }
+        }
+
+        w.len(self.sub_meshes.len())?;
+        for s in &self.sub_meshes {
+            w.str(&s.name)?;
+            w.u32(match s.kind {
+                SubMeshKind::Object => 0,
+                SubMeshKind::Group => 1,
+            })?;
+            w.usize(s.faces.start)?;
+            w.usize(s.faces.end)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_binary(r: &mut impl Read) -> io::Result<Self> {
+        let mut r = Reader(r);
+        let mut magic = [0; 4];
+        r.0.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a binary model"));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(invalid("unsupported binary model version"));
+        }
+        let mut model = Model {
+            vertexs: r.vectors()?,
+            normals: r.vectors()?,
+            texture_vertexs: r.vectors()?,
+            colors: r.vectors()?,
+            ..Default::default()
+        };
+
+        let face_count = r.len()?;
+        model.faces.reserve(face_count.min(1 << 20));
+        model.smoothing_groups.reserve(face_count.min(1 << 20));
+        for _ in 0..face_count {
+            let mut face = [(0, None, None); 3];
+            for v in &mut face {
+                *v = (r.u32()? as usize, r.index()?, r.index()?);
+            }
+            let material = r.index()?;
+            let group = match r.u8()? {
+                0 => None,
+                1 => Some(r.u32()?),
+                _ => return Err(invalid("invalid smoothing group in binary model")),
+            };
+            model.faces.push((face, material));
+            model.smoothing_groups.push(group);
+        }
+
+        for _ in 0..r.len()? {
+            let mut m = Material::new(&r.str()?);
+            [m.ambient, m.diffuse, m.specular, m.emissive] =
+                [r.vector()?, r.vector()?, r.vector()?, r.vector()?];
+            [m.shininess, m.dissolve, m.metallic, m.roughness] =
+                [r.f32()?, r.f32()?, r.f32()?, r.f32()?];
+            m.illum = r.u32()?;
+            m.alpha_mode = match r.u32()? {
+                0 => AlphaMode::Opaque,
+                1 => AlphaMode::Cutout,
+                2 => AlphaMode::Blend,
+                _ => return Err(invalid("invalid alpha mode in binary model")),
+            };
+            m.alpha_cutoff = r.f32()?;
+            let mut map = || -> io::Result<Option<PathBuf>> {
+                let s = r.str()?;
+                Ok((!s.is_empty()).then(|| PathBuf::from(s)))
+            };
+            [m.diffuse_map, m.bump_map, m.specular_map, m.alpha_map] =
+                [map()?, map()?, map()?, map()?];
+            model.insert_material(m);
+        }
+
+        for _ in 0..r.len()? {
+            let name = r.str()?;
+            let kind = match r.u32()? {
+                0 => SubMeshKind::Object,
+                1 => SubMeshKind::Group,
+                _ => return Err(invalid("invalid sub-mesh kind in binary model")),
+            };
+            let faces = r.len()?..r.len()?;
+            model.sub_meshes.push(SubMesh { name, kind, faces });
+        }
+        validate(&model)?;
+        Ok(model)
+    }
+}
+
+/// 检查面片中的序号是否越界，避免损坏的文件在使用时才出错
+fn validate(model: &Model) -> io::Result<()> {
+    let in_range = |i: Option<usize>, len: usize| i.is_none_or(|i| i < len);
+    let valid = model.faces.iter().all(|(face, material)| {
+        face.iter().all(|&(v, uv, n): &FaceVertex| {
+            v < model.vertexs.len()
+                && in_range(uv, model.texture_vertexs.len())
+                && in_range(n, model.normals.len())
+        }) && in_range(*material, model.materials.len())
+    });
+    let colors = model.colors.is_empty() || model.colors.len() == model.vertexs.len();
+    let sub_meshes = model
+        .sub_meshes
+        .iter()
+        .all(|s| s.faces.start <= s.faces.end && s.faces.end <= model.faces.len());
+    if valid && colors && sub_meshes {
+        Ok(())
+    } else {
+        Err(invalid("index out of range in binary model"))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Writer<'a, W>(&'a mut W);
+
+impl<W: Write> Writer<'_, W> {
+    fn u8(&mut self, v: u8) -> io::Result<()> {
+        self.0.write_all(&[v])
+    }
+
+    fn u32(&mut self, v: u32) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    fn f32(&mut self, v: f32) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    fn usize(&mut self, v: usize) -> io::Result<()> {
+        let v = u32::try_from(v).map_err(|_| invalid("value too large for binary model"))?;
+        self.u32(v)
+    }
+
+    fn len(&mut self, len: usize) -> io::Result<()> {
+        self.usize(len)
+    }
+
+    fn index(&mut self, i: Option<usize>) -> io::Result<()> {
+        match i {
+            Some(i) if i >= NONE as usize => Err(invalid("index too large for binary model")),
+            Some(i) => self.usize(i),
+            None => self.u32(NONE),
+        }
+    }
+
+    fn str(&mut self, s: &str) -> io::Result<()> {
+        self.len(s.len())?;
+        self.0.write_all(s.as_bytes())
+    }
+
+    fn vector<const S: usize>(&mut self, v: Vector<f32, S>) -> io::Result<()> {
+        (0..S).try_for_each(|i| self.f32(v[i]))
+    }
+
+    fn vectors<const S: usize>(&mut self, vs: &[Vector<f32, S>]) -> io::Result<()> {
+        self.len(vs.len())?;
+        vs.iter().try_for_each(|&v| self.vector(v))
+    }
+}
+
+struct Reader<'a, R>(&'a mut R);
+
+impl<R: Read> Reader<'_, R> {
+    fn u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0];
+        self.0.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.0.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        self.u32().map(|n| n as usize)
+    }
+
+    fn index(&mut self) -> io::Result<Option<usize>> {
+        self.u32().map(|i| (i != NONE).then_some(i as usize))
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.len()?;
+        let mut buf = Vec::new();
+        self.0.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(buf).map_err(|_| invalid("invalid utf-8 in binary model"))
+    }
+
+    fn vector<const S: usize>(&mut self) -> io::Result<Vector<f32, S>> {
+        let mut data = [0.0; S];
+        for v in &mut data {
+            *v = self.f32()?;
+        }
+        Ok(Vector::new(data))
+    }
+
+    fn vectors<const S: usize>(&mut self) -> io::Result<Vec<Vector<f32, S>>> {
+        let len = self.len()?;
+        // 长度来自文件，不可信，预分配设上限
+        let mut vs = Vec::with_capacity(len.min(1 << 20));
+        for _ in 0..len {
+            vs.push(self.vector()?);
+        }
+        Ok(vs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut model = Model::load_from_obj("assets/african_head.obj").unwrap();
+        let mut m = Material::new("skin");
+        m.diffuse_map = Some(PathBuf::from("assets/african_head_diffuse.tga"));
+        m.metallic = 0.5;
+        m.alpha_mode = AlphaMode::Cutout;
+        let id = model.insert_material(m);
+        model.faces[0].1 = Some(id);
+        model.smoothing_groups = vec![Some(1); model.faces.len()];
+        // 最大的平滑组不能与None混淆
+        model.smoothing_groups[1] = Some(u32::MAX);
+        model.smoothing_groups[2] = None;
+        model.sub_meshes.push(SubMesh {
+            name: "head".to_string(),
+            kind: SubMeshKind::Object,
+            faces: 1..10,
+        });
+
+        let mut buf = Vec::new();
+        model.write_binary(&mut buf).unwrap();
+        let copy = Model::read_binary(&mut buf.as_slice()).unwrap();
+        assert_eq!(copy.vertexs, model.vertexs);
+        assert_eq!(copy.texture_vertexs, model.texture_vertexs);
+        assert_eq!(copy.normals, model.normals);
+        assert_eq!(copy.faces, model.faces);
+        assert_eq!(copy.smoothing_groups, model.smoothing_groups);
+        assert_eq!(copy.sub_meshes, model.sub_meshes);
+        assert_eq!(copy.find_material("skin"), Some(id));
+        let m = copy.get_material(id);
+        assert_eq!(m.metallic, 0.5);
+        assert_eq!(m.alpha_mode, AlphaMode::Cutout);
+        assert_eq!(m.diffuse_map, model.get_material(id).diffuse_map);
+
+        // 截断或越界的数据
+        assert!(Model::read_binary(&mut &buf[..buf.len() - 1]).is_err());
+        buf[8..12].copy_from_slice(&0u32.to_le_bytes());
+        assert!(Model::read_binary(&mut buf.as_slice()).is_err());
+
+        // 超出u32的序号报错而不是截断
+        model.faces[0].0[0].0 = u32::MAX as usize + 1;
+        let e = model.write_binary(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        model.faces[0].0[0].0 = 0;
+        model.faces[0].0[0].1 = Some(NONE as usize);
+        let e = model.write_binary(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_save_binary() {
+        let model = Model::load_from_obj("assets/african_head.obj").unwrap();
+        let file = std::env::temp_dir().join(format!("tinyrenderer-{}.trmb", std::process::id()));
+        model.save(&file).unwrap();
+        let copy = Model::load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(copy.vertexs, model.vertexs);
+        assert_eq!(copy.faces, model.faces);
+        assert!(model.save(file.with_extension("png")).is_err());
+    }
+}
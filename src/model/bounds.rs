@@ -0,0 +1,179 @@
+use crate::vec::Vector3;
+
+use super::Model;
+
+/// 轴对齐包围盒
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// 各轴上的边长
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// 最长边的边长
+    pub fn max_extent(&self) -> f32 {
+        let s = self.size();
+        s.x().max(s.y()).max(s.z())
+    }
+}
+
+/// 包围球
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// 计算恰好能看到整个包围球的相机位置，相机沿direction方向看向球心
+    /// fov_y为垂直视角，aspect为宽高比，宽小于高时按水平视角计算
+    pub fn frame(&self, fov_y: f32, aspect: f32, direction: Vector3<f32>) -> Vector3<f32> {
+        let fov_x = 2.0 * ((fov_y / 2.0).tan() * aspect).atan();
+        let half = fov_y.min(fov_x) / 2.0;
+        let distance = self.radius / half.sin();
+        self.center - direction.normalize() * distance
+    }
+
+    /// 从eye处以垂直视角fov_y观察时，包围球投影到高为screen_height像素的屏幕上的直径
+    /// 相机在球内时返回无穷大
+    pub fn projected_size(&self, eye: Vector3<f32>, fov_y: f32, screen_height: f32) -> f32 {
+        let distance = (self.center - eye).norm();
+        if distance <= self.radius {
+            return f32::INFINITY;
+        }
+        self.radius / (distance * (fov_y / 2.0).tan()) * screen_height
+    }
+}
+
+impl Model {
+    /// 所有顶点坐标的轴对齐包围盒，没有顶点时返回None
+    pub fn aabb(&self) -> Option<Aabb> {
+        let first = *self.vertexs.first()?;
+        let mut aabb = Aabb {
+            min: first,
+            max: first,
+        };
+        for v in &self.vertexs {
+            for i in 0..3 {
+                aabb.min[i] = aabb.min[i].min(v[i]);
+                aabb.max[i] = aabb.max[i].max(v[i]);
+            }
+        }
+        Some(aabb)
+    }
+
+    /// 用Ritter算法求近似最小的包围球，没有顶点时返回None
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let first = *self.vertexs.first()?;
+        let farthest = |from: Vector3<f32>| {
+            self.vertexs
+                .iter()
+                .copied()
+                .max_by(|a, b| (*a - from).norm2().total_cmp(&(*b - from).norm2()))
+                .unwrap()
+        };
+        // 先以相距较远的两点为直径，再逐个包含落在球外的顶点
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = BoundingSphere {
+            center: (a + b) / 2.0,
+            radius: (b - a).norm() / 2.0,
+        };
+        for &v in &self.vertexs {
+            let d = (v - sphere.center).norm();
+            if d > sphere.radius {
+                let radius = (sphere.radius + d) / 2.0;
+                sphere.center =
+                    sphere.center + (v - sphere.center) * ((radius - sphere.radius) / d);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    /// 平移顶点坐标，使包围盒中心位于原点，返回平移量
+    pub fn recenter(&mut self) -> Vector3<f32> {
+        let Some(aabb) = self.aabb() else {
+            return Vector3::new_zero();
+        };
+        let delta = Vector3::new_zero() - aabb.center();
+        for v in &mut self.vertexs {
+            *v += delta;
+        }
+        self.refresh_tangents();
+        delta
+    }
+
+    /// 平移并等比缩放顶点坐标，使模型位于以原点为中心、边长为1的立方体内，最长边恰好为1
+    /// 等比缩放不改变法向量的方向，已生成的切线会重新生成
+    pub fn normalize_to_unit_cube(&mut self) {
+        self.recenter();
+        let extent = self.aabb().map_or(0.0, |aabb| aabb.max_extent());
+        if extent > 0.0 {
+            for v in &mut self.vertexs {
+                *v = *v / extent;
+            }
+        }
+        self.refresh_tangents();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let mut model = Model {
+            vertexs: [[1.0, 2.0, 3.0], [5.0, 2.0, 3.0], [3.0, 4.0, 4.0]]
+                .map(Vector3::new)
+                .to_vec(),
+            ..Default::default()
+        };
+        let aabb = model.aabb().unwrap();
+        assert_eq!(aabb.center(), Vector3::new([3.0, 3.0, 3.5]));
+        assert_eq!(aabb.max_extent(), 4.0);
+        let sphere = model.bounding_sphere().unwrap();
+        assert!(model
+            .vertexs
+            .iter()
+            .all(|&v| (v - sphere.center).norm() <= sphere.radius + 1e-5));
+
+        model.normalize_to_unit_cube();
+        let aabb = model.aabb().unwrap();
+        assert_eq!(aabb.min, Vector3::new([-0.5, -0.25, -0.125]));
+        assert_eq!(aabb.max, Vector3::new([0.5, 0.25, 0.125]));
+        assert!(Model::default().aabb().is_none());
+    }
+
+    #[test]
+    fn test_frame() {
+        let sphere = BoundingSphere {
+            center: Vector3::new([0.0, 0.0, -1.0]),
+            radius: 1.0,
+        };
+        // 90°视角下，距离为半径的√2倍
+        let eye = sphere.frame(PI / 2.0, 1.0, Vector3::new([0.0, 0.0, -2.0]));
+        assert!((eye.z() - (2.0f32.sqrt() - 1.0)).abs() < 1e-5);
+        // 宽比高窄时按水平视角计算，距离更远
+        let narrow = sphere.frame(PI / 2.0, 0.5, Vector3::new([0.0, 0.0, -1.0]));
+        assert!(narrow.z() > eye.z());
+
+        // 投影大小与距离成反比，相机在球内时为无穷大
+        let size = |z| sphere.projected_size(Vector3::new([0.0, 0.0, z]), PI / 2.0, 100.0);
+        assert!((size(1.0) - 50.0).abs() < 1e-4);
+        assert!((size(3.0) - 25.0).abs() < 1e-4);
+        assert!(size(-1.5).is_infinite());
+    }
+}
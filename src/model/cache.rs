@@ -0,0 +1,246 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    error::Error,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use super::Model;
+
+/// 缓存文件的文件头，之后依次是工作目录、源文件的标识、被引用文件的个数及各自的标识以及二进制格式的模型
+const MAGIC: &[u8; 4] = b"TRMC";
+
+/// 模型缓存，把解析后的模型以二进制格式保存在缓存目录中，下次加载时直接读取
+/// 缓存以工作目录以及源文件的路径、大小和修改时间为键，源文件或其引用的MTL等文件变化后自动失效
+/// 模型中的贴图路径按加载时给出的路径拼接，因此同一文件在不同工作目录下分别缓存
+#[derive(Debug, Clone)]
+pub struct ModelCache {
+    dir: PathBuf,
+}
+
+/// 文件的标识，路径为加载时给出的路径
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: String,
+    size: u64,
+    /// 修改时间，自UNIX纪元起的纳秒数
+    mtime: u128,
+}
+
+impl CacheKey {
+    fn of(file: &Path) -> io::Result<Self> {
+        let meta = fs::metadata(file)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Ok(Self {
+            path: file.to_string_lossy().into_owned(),
+            size: meta.len(),
+            mtime,
+        })
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        write_str(w, &self.path)?;
+        w.write_all(&self.size.to_le_bytes())?;
+        w.write_all(&self.mtime.to_le_bytes())
+    }
+
+    fn read(r: &mut &[u8]) -> Option<Self> {
+        let path = read_str(r)?;
+        let size = u64::from_le_bytes(take(r, 8)?.try_into().ok()?);
+        let mtime = u128::from_le_bytes(take(r, 16)?.try_into().ok()?);
+        Some(Self { path, size, mtime })
+    }
+}
+
+impl ModelCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 加载模型，缓存命中时直接读取缓存，否则按扩展名解析源文件并写入缓存
+    /// 带有内嵌贴图的模型不会被缓存
+    /// 写缓存失败不影响加载结果，错误随模型一起返回，由调用方决定如何处理
+    pub fn load(
+        &self,
+        filename: impl AsRef<Path>,
+    ) -> Result<(Model, Option<io::Error>), Box<dyn Error>> {
+        let file = filename.as_ref();
+        let cwd = env::current_dir()?.to_string_lossy().into_owned();
+        let key = CacheKey::of(file)?;
+        let mut hasher = DefaultHasher::new();
+        (&cwd, &key).hash(&mut hasher);
+        let cache = self.dir.join(format!("{:016x}.trmc", hasher.finish()));
+
+        if let Some(model) = Self::read(&cache, &cwd, &key) {
+            return Ok((model, None));
+        }
+        let model = Model::load(file)?;
+        let error = if model.embedded_textures.is_empty() {
+            self.write(&cache, &cwd, &key, &model).err()
+        } else {
+            None
+        };
+        Ok((model, error))
+    }
+
+    /// 删除所有缓存文件
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 读取缓存，缓存不存在、键不一致、被引用的文件有变化或缓存已损坏时返回None
+    fn read(cache: &Path, cwd: &str, key: &CacheKey) -> Option<Model> {
+        let data = fs::read(cache).ok()?;
+        let mut r = data.as_slice();
+        if take(&mut r, 4)? != MAGIC || read_str(&mut r)? != cwd {
+            return None;
+        }
+        if CacheKey::read(&mut r)? != *key {
+            return None;
+        }
+        let count = u32::from_le_bytes(take(&mut r, 4)?.try_into().ok()?);
+        let mut dependencies = Vec::new();
+        for _ in 0..count {
+            let dependency = CacheKey::read(&mut r)?;
+            let path = PathBuf::from(&dependency.path);
+            if CacheKey::of(&path).ok()? != dependency {
+                return None;
+            }
+            dependencies.push(path);
+        }
+        let mut model = Model::read_binary(&mut r).ok()?;
+        model.dependencies = dependencies;
+        Some(model)
+    }
+
+    /// 先写到临时文件再重命名，避免中断时留下不完整的缓存
+    fn write(&self, cache: &Path, cwd: &str, key: &CacheKey, model: &Model) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut buf = Vec::new();
+        buf.write_all(MAGIC)?;
+        write_str(&mut buf, cwd)?;
+        key.write(&mut buf)?;
+        buf.write_all(&to_u32(model.dependencies.len())?.to_le_bytes())?;
+        for dependency in &model.dependencies {
+            CacheKey::of(dependency)?.write(&mut buf)?;
+        }
+        model.write_binary(&mut buf)?;
+        let tmp = cache.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, &buf)?;
+        fs::rename(&tmp, cache)
+    }
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    w.write_all(&to_u32(s.len())?.to_le_bytes())?;
+    w.write_all(s.as_bytes())
+}
+
+fn to_u32(n: usize) -> io::Result<u32> {
+    u32::try_from(n).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "value too large for model cache",
+        )
+    })
+}
+
+fn read_str(r: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(take(r, 4)?.try_into().ok()?) as usize;
+    String::from_utf8(take(r, len)?.to_vec()).ok()
+}
+
+fn take<'a>(r: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    let (head, tail) = (r.get(..n)?, r.get(n..)?);
+    *r = tail;
+    Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn test_cache_invalidation() {
+        let dir = temp_dir("tinyrenderer-cache-test");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("tri.obj");
+        fs::write(&source, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let cache = ModelCache::new(dir.join("cache"));
+        let load = |cache: &ModelCache| {
+            let (model, error) = cache.load(&source).unwrap();
+            assert!(error.is_none());
+            model
+        };
+        assert_eq!(load(&cache).faces_count(), 1);
+        assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 1);
+        assert_eq!(load(&cache).faces_count(), 1);
+
+        // 源文件大小变化后缓存失效
+        fs::write(
+            &source,
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 2 4 3\n",
+        )
+        .unwrap();
+        assert_eq!(load(&cache).faces_count(), 2);
+
+        // 被引用的MTL文件变化后缓存同样失效
+        fs::write(dir.join("tri.mtl"), "newmtl a\nKd 1 0 0\n").unwrap();
+        fs::write(
+            &source,
+            "mtllib tri.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        assert_eq!(load(&cache).get_material(0).diffuse.y(), 0.0);
+        fs::write(dir.join("tri.mtl"), "newmtl a\nKd 1 0.5 0\n").unwrap();
+        assert_eq!(load(&cache).get_material(0).diffuse.y(), 0.5);
+
+        cache.clear().unwrap();
+        assert!(!dir.join("cache").exists());
+
+        // 缓存目录无法创建时仍然返回模型，同时返回写缓存的错误
+        fs::write(dir.join("cache"), "").unwrap();
+        let (model, error) = cache.load(&source).unwrap();
+        assert_eq!(model.faces_count(), 1);
+        assert!(error.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 对比解析文本和读取缓存的耗时：cargo test --release bench_cached_load -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_cached_load() {
+        let source = "assets/芙宁娜.obj";
+        let cache = ModelCache::new(temp_dir("tinyrenderer-cache-bench"));
+        let time = |f: &dyn Fn() -> Model| {
+            let start = Instant::now();
+            let model = f();
+            (start.elapsed(), model.faces_count())
+        };
+        let (parse, faces) = time(&|| Model::load_from_obj(source).unwrap());
+        let (cold, _) = time(&|| cache.load(source).unwrap().0);
+        let (warm, cached_faces) = time(&|| cache.load(source).unwrap().0);
+        assert_eq!(faces, cached_faces);
+        println!("{faces} faces: parse {parse:?}, parse + write cache {cold:?}, cached {warm:?}");
+        cache.clear().unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ::gltf::{
+    buffer,
+    image::{Data as ImageData, Format, Image, Source},
+    json::{self, validation},
+    mesh::Mode,
+    Document, Error, Gltf, Node,
+};
+use ::image::{DynamicImage, ImageBuffer};
+
+use crate::{
+    mat::Matrix,
+    vec::{Vector2, Vector3, Vector4},
+};
+
+use super::{AlphaMode, Material, Model, Texture};
+
+impl Model {
+    /// 加载glTF 2.0模型(.gltf/.glb)，场景中所有节点的网格按节点变换展开到同一个模型中
+    /// 外部贴图以文件路径引用，glb或data URI内嵌的贴图解码后通过`take_embedded_textures`取出
+    pub fn load_from_gltf(filename: impl AsRef<Path>) -> Result<Self, Error> {
+        let filename = filename.as_ref();
+        let data = fs::read(filename).map_err(Error::Io)?;
+        parse_gltf(&data, filename)
+    }
+}
+
+fn parse_gltf(data: &[u8], file: &Path) -> Result<Model, Error> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let Gltf { document, blob } = Gltf::from_slice(data)?;
+    let buffers = ::gltf::import_buffers(&document, Some(dir), blob)?;
+    let mut loader = GltfLoader {
+        model: Model::default(),
+        file,
+        dir,
+        buffers: &buffers,
+        images: HashMap::new(),
+        visited: HashSet::new(),
+    };
+    // 材质按顺序加入材质表，材质id即glTF中的材质序号
+    for material in document.materials() {
+        loader.material(&material)?;
+    }
+    loader.scene(&document)?;
+    loader.model.smoothing_groups = vec![None; loader.model.faces.len()];
+    Ok(loader.model)
+}
+
+struct GltfLoader<'a> {
+    model: Model,
+    file: &'a Path,
+    dir: &'a Path,
+    buffers: &'a [buffer::Data],
+    /// 已处理的图片序号到贴图路径的映射
+    images: HashMap<usize, PathBuf>,
+    /// 已展开的节点序号，节点层级必须是树，再次遇到说明有环或多个父节点
+    visited: HashSet<usize>,
+}
+
+impl GltfLoader<'_> {
+    fn scene(&mut self, document: &Document) -> Result<(), Error> {
+        match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => {
+                for node in scene.nodes() {
+                    self.node(&node, Matrix::identity())?;
+                }
+            }
+            // 没有场景时直接加载所有网格
+            None => {
+                for mesh in document.meshes() {
+                    self.mesh(&mesh, Matrix::identity())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node, parent: Matrix<f32, 4, 4>) -> Result<(), Error> {
+        if !self.visited.insert(node.index()) {
+            return Err(invalid(
+                json::Path::new().field("nodes").index(node.index()),
+                validation::Error::Invalid,
+            ));
+        }
+        // glTF的矩阵按列存放
+        let m = node.transform().matrix();
+        let local =
+            Matrix::new([0, 1, 2, 3].map(|r| Vector4::new([m[0][r], m[1][r], m[2][r], m[3][r]])));
+        let transform = parent * local;
+        if let Some(mesh) = node.mesh() {
+            self.mesh(&mesh, transform)?;
+        }
+        for child in node.children() {
+            self.node(&child, transform)?;
+        }
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &::gltf::Mesh, transform: Matrix<f32, 4, 4>) -> Result<(), Error> {
+        let model = &mut self.model;
+        // 法向量变换使用左上3x3的伴随矩阵，与逆转置只差一个行列式的倍数
+        let axis = |c: usize| Vector3::new([0, 1, 2].map(|r| transform.get(r, c)));
+        let (c0, c1, c2) = (axis(0), axis(1), axis(2));
+        let det = c0.dot(c1.cross(c2));
+        let cofactor = [c1.cross(c2), c2.cross(c0), c0.cross(c1)].map(|c| c * det.signum());
+        // 镜像变换会翻转绕序
+        let flip = det < 0.0;
+
+        for primitive in mesh.primitives() {
+            // 点和线不参与三角形绘制，在写入顶点之前跳过
+            if !matches!(
+                primitive.mode(),
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            ) {
+                continue;
+            }
+            let path = || {
+                json::Path::new()
+                    .field("meshes")
+                    .index(mesh.index())
+                    .field("primitives")
+                    .index(primitive.index())
+            };
+            let reader = primitive.reader(|b| Some(&self.buffers[b.index()].0[..]));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let count = positions.len();
+            // 各属性的个数必须与顶点坐标相同，否则面片的索引会指到其他图元的数据
+            let attribute = |name: &str, len: usize| {
+                if len == count {
+                    Ok(())
+                } else {
+                    Err(invalid(
+                        path().field("attributes").key(name),
+                        validation::Error::Invalid,
+                    ))
+                }
+            };
+            let normals = reader.read_normals().map(Vec::from_iter);
+            if let Some(normals) = &normals {
+                attribute("NORMAL", normals.len())?;
+            }
+            let uvs = reader
+                .read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+            if let Some(uvs) = &uvs {
+                attribute("TEXCOORD_0", uvs.len())?;
+            }
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..count).collect::<Vec<_>>(),
+            };
+            if indices.iter().any(|&i| i >= count) {
+                return Err(invalid(
+                    path().field("indices"),
+                    validation::Error::IndexOutOfBounds,
+                ));
+            }
+
+            let base = model.vertexs.len();
+            let normal_base = model.normals.len();
+            let uv_base = model.texture_vertexs.len();
+            model
+                .vertexs
+                .extend(positions.map(|p| {
+                    Vector3::from_homo_coord(transform * Vector3::new(p).to_homo_coord())
+                }));
+            let has_normals = normals.is_some();
+            model.normals.extend(normals.into_iter().flatten().map(|n| {
+                let n = Vector3::new(n);
+                let n = cofactor[0] * n.x() + cofactor[1] * n.y() + cofactor[2] * n.z();
+                if n.norm2() > 0.0 {
+                    n.normalize()
+                } else {
+                    n
+                }
+            }));
+            // glTF的UV原点在左上角，转换为OBJ的左下角约定
+            let has_uvs = uvs.is_some();
+            model.texture_vertexs.extend(
+                uvs.into_iter()
+                    .flatten()
+                    .map(|[u, v]| Vector2::new([u, 1.0 - v])),
+            );
+            let triangles = match primitive.mode() {
+                Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                    .map(|i| match i % 2 {
+                        0 => [indices[i], indices[i + 1], indices[i + 2]],
+                        _ => [indices[i + 1], indices[i], indices[i + 2]],
+                    })
+                    .collect(),
+                Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                    .map(|i| [indices[0], indices[i], indices[i + 1]])
+                    .collect(),
+                _ => indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect::<Vec<_>>(),
+            };
+
+            let material = primitive.material().index();
+            for mut t in triangles {
+                if flip {
+                    t.swap(1, 2);
+                }
+                let face = t.map(|i| {
+                    (
+                        base + i,
+                        has_uvs.then_some(uv_base + i),
+                        has_normals.then_some(normal_base + i),
+                    )
+                });
+                model.faces.push((face, material));
+            }
+        }
+        Ok(())
+    }
+
+    fn material(&mut self, material: &::gltf::Material) -> Result<(), Error> {
+        let index = material.index().unwrap_or(0);
+        let name = match material.name() {
+            Some(name) => name.to_string(),
+            None => format!("material{index}"),
+        };
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let mut m = Material::new(&self.model.unique_material_name(&name));
+        m.diffuse = Vector3::new([r, g, b]);
+        m.dissolve = a;
+        m.emissive = Vector3::new(material.emissive_factor());
+        m.metallic = pbr.metallic_factor();
+        m.roughness = pbr.roughness_factor();
+        m.alpha_mode = match material.alpha_mode() {
+            ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            ::gltf::material::AlphaMode::Mask => AlphaMode::Cutout,
+            ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+        m.alpha_cutoff = material.alpha_cutoff().unwrap_or(0.5);
+        if let Some(info) = pbr.base_color_texture() {
+            m.diffuse_map = Some(self.image(&info.texture().source())?);
+        }
+        if let Some(normal) = material.normal_texture() {
+            m.bump_map = Some(self.image(&normal.texture().source())?);
+        }
+        self.model.insert_material(m);
+        Ok(())
+    }
+
+    /// 外部图片返回其文件路径，内嵌图片解码后以`文件名#image序号`为路径保存
+    fn image(&mut self, image: &Image) -> Result<PathBuf, Error> {
+        if let Some(path) = self.images.get(&image.index()) {
+            return Ok(path.clone());
+        }
+        let path = match image.source() {
+            Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                self.dir.join(percent_decode(uri))
+            }
+            source => {
+                let data = ImageData::from_source(source, Some(self.dir), self.buffers)?;
+                let path = PathBuf::from(format!("{}#image{}", self.file.display(), image.index()));
+                if let Some(img) = dynamic_image(data) {
+                    self.model
+                        .embedded_textures
+                        .insert(path.clone(), Texture::from_image(&img));
+                }
+                path
+            }
+        };
+        self.images.insert(image.index(), path.clone());
+        Ok(path)
+    }
+}
+
+fn invalid(path: json::Path, error: validation::Error) -> Error {
+    Error::Validation(vec![(path, error)])
+}
+
+/// 解码URI中的`%XX`转义
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 将glTF解码出的像素数据转换为`DynamicImage`，16位数据按本机字节序存放
+fn dynamic_image(data: ImageData) -> Option<DynamicImage> {
+    let (w, h) = (data.width, data.height);
+    let u16s = |p: &[u8]| {
+        p.chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>()
+    };
+    let f32s = |p: &[u8]| {
+        p.chunks_exact(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect::<Vec<_>>()
+    };
+    match data.format {
+        Format::R8 => ImageBuffer::from_raw(w, h, data.pixels).map(DynamicImage::ImageLuma8),
+        Format::R8G8 => ImageBuffer::from_raw(w, h, data.pixels).map(DynamicImage::ImageLumaA8),
+        Format::R8G8B8 => ImageBuffer::from_raw(w, h, data.pixels).map(DynamicImage::ImageRgb8),
+        Format::R8G8B8A8 => ImageBuffer::from_raw(w, h, data.pixels).map(DynamicImage::ImageRgba8),
+        Format::R16 => {
+            ImageBuffer::from_raw(w, h, u16s(&data.pixels)).map(DynamicImage::ImageLuma16)
+        }
+        Format::R16G16 => {
+            ImageBuffer::from_raw(w, h, u16s(&data.pixels)).map(DynamicImage::ImageLumaA16)
+        }
+        Format::R16G16B16 => {
+            ImageBuffer::from_raw(w, h, u16s(&data.pixels)).map(DynamicImage::ImageRgb16)
+        }
+        Format::R16G16B16A16 => {
+            ImageBuffer::from_raw(w, h, u16s(&data.pixels)).map(DynamicImage::ImageRgba16)
+        }
+        Format::R32G32B32FLOAT => {
+            ImageBuffer::from_raw(w, h, f32s(&data.pixels)).map(DynamicImage::ImageRgb32F)
+        }
+        Format::R32G32B32A32FLOAT => {
+            ImageBuffer::from_raw(w, h, f32s(&data.pixels)).map(DynamicImage::ImageRgba32F)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 拼装glb：一个三角形网格被两个节点引用，第二个节点平移并沿x轴镜像
+    fn sample_glb() -> Vec<u8> {
+        let mut bin = Vec::new();
+        for f in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend(f.to_le_bytes());
+        }
+        for f in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            bin.extend(f.to_le_bytes());
+        }
+        for i in [0u16, 1, 2, 0] {
+            bin.extend(i.to_le_bytes());
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": {}}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 24}},
+                    {{"buffer": 0, "byteOffset": 60, "byteLength": 6}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                    {{"bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ],
+                "images": [{{"uri": "tex/base%20color.png"}}],
+                "textures": [{{"source": 0}}],
+                "materials": [{{
+                    "name": "body",
+                    "pbrMetallicRoughness": {{
+                        "baseColorFactor": [1, 0.5, 0.25, 0.5],
+                        "baseColorTexture": {{"index": 0}},
+                        "metallicFactor": 0.25
+                    }},
+                    "alphaMode": "MASK",
+                    "alphaCutoff": 0.25
+                }}],
+                "meshes": [{{"primitives": [{{
+                    "attributes": {{"POSITION": 0, "TEXCOORD_0": 1}},
+                    "indices": 2,
+                    "material": 0
+                }}]}}],
+                "nodes": [
+                    {{"mesh": 0}},
+                    {{"mesh": 0, "translation": [0, 0, 2], "scale": [-1, 1, 1]}},
+                    {{"children": [0, 1]}}
+                ],
+                "scenes": [{{"nodes": [2]}}],
+                "scene": 0
+            }}"#,
+            bin.len()
+        );
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+        glb
+    }
+
+    #[test]
+    fn test_parse_glb() {
+        let model = parse_gltf(&sample_glb(), Path::new("assets/a.glb")).unwrap();
+        assert_eq!(model.vertexs_count(), 6);
+        assert_eq!(model.faces_count(), 2);
+
+        let m = model.get_material(0);
+        assert_eq!(m.name, "body");
+        assert_eq!((m.dissolve, m.metallic, m.roughness), (0.5, 0.25, 1.0));
+        assert_eq!((m.alpha_mode, m.alpha_cutoff), (AlphaMode::Cutout, 0.25));
+        assert_eq!(
+            m.diffuse_map,
+            Some(PathBuf::from("assets/tex/base color.png"))
+        );
+
+        // UV的v方向翻转
+        let (face, material) = model.get_face(0);
+        assert_eq!(material, Some(0));
+        assert_eq!(model.get_uv(face[0].1.unwrap()).y(), 1.0);
+
+        // 镜像节点的顶点经过变换，绕序翻转后面片法向量保持朝外
+        let (face, _) = model.get_face(1);
+        let p = model.get_vertex(face[1].0);
+        assert_eq!([p.x(), p.y(), p.z()], [0.0, 1.0, 2.0]);
+        assert_eq!(model.get_face_normal(1).z(), 1.0);
+        assert_eq!(model.get_face_normal(0).z(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_primitive() {
+        // 替换为等长的内容，不影响glb中各段的长度
+        let replace = |from: &[u8], to: &[u8]| {
+            let mut glb = sample_glb();
+            let i = glb.windows(from.len()).position(|w| w == from).unwrap();
+            glb[i..i + to.len()].copy_from_slice(to);
+            parse_gltf(&glb, Path::new("a.glb"))
+        };
+        // UV个数与顶点坐标不同
+        let uvs = replace(
+            br#""count": 3, "type": "VEC2""#,
+            br#""count": 2, "type": "VEC2""#,
+        );
+        assert!(matches!(uvs, Err(Error::Validation(_))));
+        // 索引超出顶点个数
+        let mut glb = sample_glb();
+        let len = glb.len();
+        glb[len - 4..len - 2].copy_from_slice(&7u16.to_le_bytes());
+        let indices = parse_gltf(&glb, Path::new("a.glb"));
+        assert!(matches!(indices, Err(Error::Validation(_))));
+        // 节点的子节点中包含自身
+        let cycle = replace(br#"{"children": [0, 1]}"#, br#"{"children": [2, 1]}"#);
+        assert!(matches!(cycle, Err(Error::Validation(_))));
+        // 线段图元不留下顶点
+        let lines = replace(br#""indices": 2,"#, br#""mode": 1,   "#).unwrap();
+        assert_eq!((lines.vertexs_count(), lines.faces_count()), (0, 0));
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::vec::{Vector2, Vector3};
+
+use super::{FaceVertex, Model};
+
+/// 索引顶点缓冲中的一个顶点
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: Vector3<f32>,
+    /// 缺少UV时为(0, 0)
+    pub uv: Vector2<f32>,
+    /// 缺少法向量时为面片法向量
+    pub normal: Vector3<f32>,
+    /// 顶点颜色，模型没有顶点颜色时为白色
+    pub color: Vector3<f32>,
+    /// 切线与副切线，模型没有生成切线时为0
+    pub tangent: Vector3<f32>,
+    pub bitangent: Vector3<f32>,
+}
+
+/// 去重后的顶点缓冲与索引缓冲，每个顶点只需变换一次
+#[derive(Debug, Clone, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    /// 每3个索引构成一个三角形，第i个三角形对应模型的第i个面片
+    pub indices: Vec<u32>,
+}
+
+impl IndexedMesh {
+    /// 第index个三角形的三个顶点序号
+    pub fn triangle(&self, index: usize) -> [usize; 3] {
+        let i = &self.indices[index * 3..index * 3 + 3];
+        [i[0] as usize, i[1] as usize, i[2] as usize]
+    }
+}
+
+impl Model {
+    /// 把(坐标序号, UV坐标序号, 法向量序号)相同的面片顶点合并为一个顶点，生成索引顶点缓冲
+    /// 缺少法向量的面片顶点使用面片法向量，不与其他面片共享
+    /// 已生成切线时，切线不同的面片顶点(如UV镜像的两侧)也不共享
+    pub fn to_indexed(&self) -> IndexedMesh {
+        let mut mesh = IndexedMesh {
+            vertices: Vec::with_capacity(self.vertexs.len()),
+            indices: Vec::with_capacity(self.faces.len() * 3),
+        };
+        let has_tangents = self.has_tangents();
+        // 缺少法向量时键中带上面片序号，有切线时带上切线
+        let mut ids = HashMap::<(FaceVertex, Option<usize>, Option<[u32; 4]>), u32>::new();
+        for (i, (face, _)) in self.faces.iter().enumerate() {
+            for (j, &v) in face.iter().enumerate() {
+                let tangent = has_tangents.then(|| {
+                    let t = self.get_tangent(i, j);
+                    [t.x(), t.y(), t.z(), t.w()].map(f32::to_bits)
+                });
+                let key = (v, v.2.is_none().then_some(i), tangent);
+                let id = *ids.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(Vertex {
+                        position: self.vertexs[v.0],
+                        uv: v
+                            .1
+                            .map_or(Vector2::new_zero(), |uv| self.texture_vertexs[uv]),
+                        normal: self.corner_normal(i, j),
+                        color: self
+                            .get_vertex_color(v.0)
+                            .unwrap_or(Vector3::new([1.0, 1.0, 1.0])),
+                        tangent: tangent.map_or(Vector3::new_zero(), |_| {
+                            let t = self.get_tangent(i, j);
+                            Vector3::new([t.x(), t.y(), t.z()])
+                        }),
+                        bitangent: tangent
+                            .map_or(Vector3::new_zero(), |_| self.get_bitangent(i, j)),
+                    });
+                    u32::try_from(mesh.vertices.len() - 1).expect("too many vertices")
+                });
+                mesh.indices.push(id);
+            }
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_vertices() {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let model = Model {
+            vertexs: corners.map(|p| Vector3::new([p[0], p[1], 0.0])).to_vec(),
+            texture_vertexs: corners.map(Vector2::new).to_vec(),
+            normals: vec![Vector3::new([0.0, 0.0, 1.0])],
+            faces: vec![
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (1, Some(1), Some(0)),
+                        (2, Some(2), Some(0)),
+                    ],
+                    None,
+                ),
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (2, Some(2), Some(0)),
+                        (3, Some(3), Some(0)),
+                    ],
+                    None,
+                ),
+                // 缺少法向量，不与前两个面片共享顶点
+                ([(0, None, None), (3, None, None), (2, None, None)], None),
+            ],
+            ..Default::default()
+        };
+        let mesh = model.to_indexed();
+        assert_eq!(mesh.indices.len(), 9);
+        assert_eq!(mesh.vertices.len(), 7);
+        assert_eq!(mesh.triangle(1), [0, 2, 3]);
+        assert_eq!(mesh.vertices[3].uv, Vector2::new([0.0, 1.0]));
+        let n = mesh.vertices[mesh.triangle(2)[0]].normal;
+        assert_eq!([n.x(), n.y(), n.z()], [0.0, 0.0, -1.0]);
+        assert_eq!(mesh.vertices[6].color, Vector3::new([1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_mirrored_tangents() {
+        // 第二个面片的UV左右镜像，与第一个面片共用的两个顶点切线不同
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let mut model = Model {
+            vertexs: corners.map(|p| Vector3::new([p[0], p[1], 0.0])).to_vec(),
+            texture_vertexs: [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [2.0, 1.0]]
+                .map(Vector2::new)
+                .to_vec(),
+            normals: vec![Vector3::new([0.0, 0.0, 1.0])],
+            faces: vec![
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (1, Some(1), Some(0)),
+                        (2, Some(2), Some(0)),
+                    ],
+                    None,
+                ),
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (2, Some(2), Some(0)),
+                        (3, Some(3), Some(0)),
+                    ],
+                    None,
+                ),
+            ],
+            ..Default::default()
+        };
+        let mesh = model.to_indexed();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.vertices[0].tangent, Vector3::new_zero());
+
+        model.generate_tangents();
+        let mesh = model.to_indexed();
+        assert_eq!(mesh.vertices.len(), 6);
+        let [a, b] = [mesh.triangle(0)[0], mesh.triangle(1)[0]].map(|i| mesh.vertices[i]);
+        assert_eq!(a.tangent, Vector3::new([1.0, 0.0, 0.0]));
+        assert_eq!(b.tangent, Vector3::new([-1.0, 0.0, 0.0]));
+        assert_eq!(a.bitangent, Vector3::new([0.0, 1.0, 0.0]));
+        assert_eq!(b.bitangent, Vector3::new([0.0, 1.0, 0.0]));
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+};
+
+use crate::vec::Vector3;
+
+use super::obj::{Line, ObjParseError};
+
+/// 透明度的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// 忽略透明度
+    Opaque,
+    /// 透明度低于阈值的像素丢弃，其余不透明
+    Cutout,
+    /// 按透明度与背景混合
+    Blend,
+}
+
+/// MTL材质
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    /// 环境光颜色(Ka)
+    pub ambient: Vector3<f32>,
+    /// 漫反射颜色(Kd)
+    pub diffuse: Vector3<f32>,
+    /// 镜面反射颜色(Ks)
+    pub specular: Vector3<f32>,
+    /// 镜面反射指数(Ns)
+    pub shininess: f32,
+    /// 不透明度(d)，1为完全不透明
+    pub dissolve: f32,
+    /// 光照模型(illum)
+    pub illum: u32,
+    /// 自发光颜色(Ke)
+    pub emissive: Vector3<f32>,
+    /// PBR金属度(Pm)
+    pub metallic: f32,
+    /// PBR粗糙度(Pr)
+    pub roughness: f32,
+    /// 漫反射贴图(map_Kd)
+    pub diffuse_map: Option<PathBuf>,
+    /// 凹凸/法线贴图(map_Bump)
+    pub bump_map: Option<PathBuf>,
+    /// 镜面反射贴图(map_Ks)
+    pub specular_map: Option<PathBuf>,
+    /// 透明度贴图(map_d)
+    pub alpha_map: Option<PathBuf>,
+    /// 透明度的处理方式，MTL中有map_d时为Cutout，否则d小于1时为Blend
+    pub alpha_mode: AlphaMode,
+    /// Cutout模式下的透明度阈值，取值范围0~1
+    pub alpha_cutoff: f32,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: Vector3::new_zero(),
+            diffuse: Vector3::new([1.0, 1.0, 1.0]),
+            specular: Vector3::new_zero(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            illum: 2,
+            emissive: Vector3::new_zero(),
+            metallic: 0.0,
+            roughness: 1.0,
+            diffuse_map: None,
+            bump_map: None,
+            specular_map: None,
+            alpha_map: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+        }
+    }
+}
+
+/// 贴图语句中带参数的选项及其参数个数，-o/-s/-t最多3个参数
+const MAP_OPTIONS: [(&str, usize); 12] = [
+    ("-blendu", 1),
+    ("-blendv", 1),
+    ("-bm", 1),
+    ("-boost", 1),
+    ("-cc", 1),
+    ("-clamp", 1),
+    ("-imfchan", 1),
+    ("-mm", 2),
+    ("-o", 3),
+    ("-s", 3),
+    ("-t", 3),
+    ("-texres", 1),
+];
+
+/// 解析MTL文本内容，贴图路径相对于MTL文件所在目录解析
+/// 宽松模式下出错的行会被跳过并记录到`warnings`中
+pub(super) fn parse_mtl(
+    s: &str,
+    file: &Path,
+    lenient: bool,
+    warnings: &mut Vec<ObjParseError>,
+) -> Result<Vec<Material>, ObjParseError> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut materials = Vec::<Material>::new();
+    for (i, text) in s.lines().enumerate() {
+        let line = Line {
+            file,
+            number: i + 1,
+            text,
+        };
+        if let Err(e) = parse_line(&line, dir, &mut materials) {
+            if lenient {
+                warnings.push(e);
+            } else {
+                return Err(e);
+            }
+        }
+    }
+    // MTL没有透明模式的语句，由透明度和透明度贴图推断
+    for m in &mut materials {
+        if m.alpha_map.is_some() {
+            m.alpha_mode = AlphaMode::Cutout;
+        } else if m.dissolve < 1.0 {
+            m.alpha_mode = AlphaMode::Blend;
+        }
+    }
+    Ok(materials)
+}
+
+fn parse_line<'a>(
+    line: &Line<'a>,
+    dir: &Path,
+    materials: &mut Vec<Material>,
+) -> Result<(), ObjParseError> {
+    let mut tokens = line.text.split_whitespace();
+    let Some(first_flag) = tokens.next() else {
+        return Ok(());
+    };
+    if first_flag == "newmtl" {
+        let name = tokens.next().ok_or_else(|| line.missing())?;
+        materials.push(Material::new(name));
+        return Ok(());
+    }
+    // newmtl之前的语句没有可归属的材质，直接忽略
+    let Some(material) = materials.last_mut() else {
+        return Ok(());
+    };
+    let color = |tokens: &mut SplitWhitespace<'a>| -> Result<Vector3<f32>, ObjParseError> {
+        let r = line.next_f32(tokens)?;
+        // 只给出一个分量时表示灰度
+        let (g, b) = match tokens.clone().next() {
+            Some(_) => (line.next_f32(tokens)?, line.next_f32(tokens)?),
+            None => (r, r),
+        };
+        Ok(Vector3::new([r, g, b]))
+    };
+    match first_flag {
+        "Ka" => material.ambient = color(&mut tokens)?,
+        "Kd" => material.diffuse = color(&mut tokens)?,
+        "Ks" => material.specular = color(&mut tokens)?,
+        "Ns" => material.shininess = line.next_f32(&mut tokens)?,
+        "d" => material.dissolve = line.next_f32(&mut tokens)?,
+        "Tr" => material.dissolve = 1.0 - line.next_f32(&mut tokens)?,
+        "illum" => material.illum = line.next_u32(&mut tokens)?,
+        "Ke" => material.emissive = color(&mut tokens)?,
+        "Pm" => material.metallic = line.next_f32(&mut tokens)?,
+        "Pr" => material.roughness = line.next_f32(&mut tokens)?,
+        "map_Kd" => material.diffuse_map = Some(map_path(line, dir)?),
+        "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(map_path(line, dir)?),
+        "map_Ks" => material.specular_map = Some(map_path(line, dir)?),
+        "map_d" => material.alpha_map = Some(map_path(line, dir)?),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 跳过贴图选项，取行尾剩余部分作为文件名（文件名中可以包含空格）
+fn map_path(line: &Line, dir: &Path) -> Result<PathBuf, ObjParseError> {
+    let mut tokens = line.text.split_whitespace().skip(1).peekable();
+    while let Some(&token) = tokens.peek() {
+        let Some(&(_, count)) = MAP_OPTIONS.iter().find(|(o, _)| *o == token) else {
+            break;
+        };
+        tokens.next();
+        for _ in 0..count {
+            // -o/-s/-t的参数个数可变，遇到非数字即停止
+            match tokens.peek() {
+                Some(arg) if count < 3 || arg.parse::<f32>().is_ok() => {
+                    tokens.next();
+                }
+                _ => break,
+            }
+        }
+    }
+    let name = tokens.next().ok_or_else(|| line.missing())?;
+    let offset = name.as_ptr() as usize - line.text.as_ptr() as usize;
+    // Windows下导出的文件常用反斜杠作为路径分隔符
+    let name = line.text[offset..].trim_end().replace('\\', "/");
+    Ok(dir.join(name))
+}
+
+/// 写出MTL文本，贴图路径尽量写成相对于`dir`的路径
+pub(super) fn write_mtl(materials: &[Material], w: &mut impl Write, dir: &Path) -> io::Result<()> {
+    let color = |c: Vector3<f32>| format!("{} {} {}", c.x(), c.y(), c.z());
+    for m in materials {
+        writeln!(w, "newmtl {}", m.name)?;
+        writeln!(w, "Ka {}", color(m.ambient))?;
+        writeln!(w, "Kd {}", color(m.diffuse))?;
+        writeln!(w, "Ks {}", color(m.specular))?;
+        writeln!(w, "Ke {}", color(m.emissive))?;
+        writeln!(w, "Ns {}", m.shininess)?;
+        writeln!(w, "d {}", m.dissolve)?;
+        writeln!(w, "illum {}", m.illum)?;
+        writeln!(w, "Pm {}", m.metallic)?;
+        writeln!(w, "Pr {}", m.roughness)?;
+        let maps = [
+            ("map_Kd", &m.diffuse_map),
+            ("map_Bump", &m.bump_map),
+            ("map_Ks", &m.specular_map),
+            ("map_d", &m.alpha_map),
+        ];
+        for (statement, map) in maps {
+            if let Some(path) = map {
+                let path = path.strip_prefix(dir).unwrap_or(path);
+                writeln!(
+                    w,
+                    "{statement} {}",
+                    path.to_string_lossy().replace('\\', "/")
+                )?;
+            }
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::obj::ObjErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_mtl() {
+        let s = "# comment\n\
+                 newmtl 体\n\
+                 Ka 0.1\n\
+                 Kd 0.5 0.6 0.7\n\
+                 Ns 10\n\
+                 Tr 0.25\n\
+                 illum 1\n\
+                 Pr 0.5\n\
+                 map_Kd -s 1 1 1 -clamp on tex\\体 1.png\n\
+                 map_Bump -bm 0.5 normal.png\n\
+                 newmtl 髮\n\
+                 map_d alpha.png\n";
+        let materials = parse_mtl(s, Path::new("assets/a.mtl"), false, &mut Vec::new()).unwrap();
+        assert_eq!(materials.len(), 2);
+        let m = &materials[0];
+        assert_eq!(m.name, "体");
+        assert_eq!(m.ambient.x(), 0.1);
+        assert_eq!(m.ambient.z(), 0.1);
+        assert_eq!(m.diffuse.y(), 0.6);
+        assert_eq!((m.shininess, m.dissolve, m.illum), (10.0, 0.75, 1));
+        assert_eq!((m.metallic, m.roughness), (0.0, 0.5));
+        assert_eq!(m.diffuse_map, Some(PathBuf::from("assets/tex/体 1.png")));
+        assert_eq!(m.bump_map, Some(PathBuf::from("assets/normal.png")));
+        assert_eq!(
+            materials[1].alpha_map,
+            Some(PathBuf::from("assets/alpha.png"))
+        );
+        assert_eq!(m.alpha_mode, AlphaMode::Blend);
+        assert_eq!(materials[1].alpha_mode, AlphaMode::Cutout);
+    }
+
+    #[test]
+    fn test_write_mtl() {
+        let mut m = Material::new("体");
+        m.diffuse = Vector3::new([0.5, 0.25, 1.0]);
+        m.roughness = 0.5;
+        m.diffuse_map = Some(PathBuf::from("out/tex/体 1.png"));
+        let mut buf = Vec::new();
+        write_mtl(&[m], &mut buf, Path::new("out")).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.contains("map_Kd tex/体 1.png\n"));
+
+        let materials = parse_mtl(&s, Path::new("out/a.mtl"), false, &mut Vec::new()).unwrap();
+        let m = &materials[0];
+        assert_eq!((m.diffuse.y(), m.roughness), (0.25, 0.5));
+        assert_eq!(m.diffuse_map, Some(PathBuf::from("out/tex/体 1.png")));
+    }
+
+    #[test]
+    fn test_mtl_errors() {
+        let s = "newmtl a\nKd 1 x 1\nNs 2\n";
+        let e = parse_mtl(s, Path::new("a.mtl"), false, &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.kind),
+            (2, 6, ObjErrorKind::InvalidFloat)
+        );
+
+        let mut warnings = Vec::new();
+        let materials = parse_mtl(s, Path::new("a.mtl"), true, &mut warnings).unwrap();
+        assert_eq!(materials[0].shininess, 2.0);
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::vec::Vector3;
+
+use super::Model;
+
+/// 法向量生成方式
+#[derive(Debug, Clone, Copy)]
+pub enum NormalMode {
+    /// 每个面片的三个顶点都使用面片自身的法向量
+    Flat,
+    /// 共享同一顶点坐标的相邻面片法向量加权平均
+    Smooth {
+        weighting: NormalWeighting,
+        /// 折痕角（弧度），与当前面片法向量夹角超过该值的相邻面片不参与平均
+        crease_angle: Option<f32>,
+    },
+}
+
+/// 平滑法向量时相邻面片的权重
+#[derive(Debug, Clone, Copy)]
+pub enum NormalWeighting {
+    /// 按面片面积加权
+    Area,
+    /// 按面片在该顶点处的内角加权
+    Angle,
+}
+
+impl Model {
+    /// 是否每个面片顶点都有法向量
+    pub fn has_normals(&self) -> bool {
+        self.faces
+            .iter()
+            .all(|(face, _)| face.iter().all(|v| v.2.is_some()))
+    }
+
+    /// 重新生成所有面片顶点的法向量，替换原有的法向量，已生成的切线会随之重新生成
+    /// 平滑模式下只有处于同一平滑组(`s`)的面片之间才会平滑，平滑组为0(`s off`)的面片使用面片法向量
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        self.replace_normals(mode);
+        self.refresh_tangents();
+    }
+
+    fn replace_normals(&mut self, mode: NormalMode) {
+        // 未归一化的叉积，长度为面片面积的两倍
+        let crosses = self
+            .faces
+            .iter()
+            .map(|(face, _)| {
+                let [a, b, c] = face.map(|v| self.vertexs[v.0]);
+                (b - a).cross(c - a)
+            })
+            .collect::<Vec<_>>();
+        let units = crosses
+            .iter()
+            .map(|n| safe_normalize(*n))
+            .collect::<Vec<_>>();
+
+        let (weighting, crease_angle) = match mode {
+            NormalMode::Flat => {
+                self.normals = units;
+                for (i, (face, _)) in self.faces.iter_mut().enumerate() {
+                    face.iter_mut().for_each(|v| v.2 = Some(i));
+                }
+                return;
+            }
+            NormalMode::Smooth {
+                weighting,
+                crease_angle,
+            } => (weighting, crease_angle),
+        };
+        let min_cos = crease_angle.map_or(-1.0, f32::cos);
+
+        // 每个顶点坐标所在的(面片序号, 角序号)
+        let mut adjacency = vec![Vec::new(); self.vertexs.len()];
+        for (i, (face, _)) in self.faces.iter().enumerate() {
+            for (j, v) in face.iter().enumerate() {
+                adjacency[v.0].push((i, j));
+            }
+        }
+        let weighted = |i: usize, j: usize| match weighting {
+            NormalWeighting::Area => crosses[i] * 0.5,
+            NormalWeighting::Angle => {
+                let face = &self.faces[i].0;
+                let p = self.vertexs[face[j].0];
+                let e1 = safe_normalize(self.vertexs[face[(j + 1) % 3].0] - p);
+                let e2 = safe_normalize(self.vertexs[face[(j + 2) % 3].0] - p);
+                units[i] * e1.dot(e2).clamp(-1.0, 1.0).acos()
+            }
+        };
+
+        let mut normals = Vec::new();
+        // 按比特去重，完全相同的法向量只存一份
+        let mut normal_ids = HashMap::new();
+        let mut corners = Vec::with_capacity(self.faces.len());
+        for (i, (face, _)) in self.faces.iter().enumerate() {
+            let group = self.smoothing_group(i);
+            let corner = face.map(|v| {
+                if group == Some(0) {
+                    return units[i];
+                }
+                let sum = adjacency[v.0]
+                    .iter()
+                    .filter(|&&(k, _)| {
+                        self.smoothing_group(k) == group && units[k].dot(units[i]) >= min_cos
+                    })
+                    .fold(Vector3::new_zero(), |acc, &(k, j)| acc + weighted(k, j));
+                if sum.norm2() > 0.0 {
+                    sum.normalize()
+                } else {
+                    units[i]
+                }
+            });
+            corners.push(corner.map(|n| {
+                *normal_ids
+                    .entry([n.x(), n.y(), n.z()].map(f32::to_bits))
+                    .or_insert_with(|| {
+                        normals.push(n);
+                        normals.len() - 1
+                    })
+            }));
+        }
+        for ((face, _), ids) in self.faces.iter_mut().zip(corners) {
+            for (v, id) in face.iter_mut().zip(ids) {
+                v.2 = Some(id);
+            }
+        }
+        self.normals = normals;
+    }
+}
+
+/// 归一化，零向量保持不变
+pub(super) fn safe_normalize(v: Vector3<f32>) -> Vector3<f32> {
+    if v.norm2() > 0.0 {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use super::*;
+
+    /// 沿x轴折成直角的两个三角形，共享边(0,1)
+    fn hinge(groups: [Option<u32>; 2]) -> Model {
+        Model {
+            vertexs: vec![
+                Vector3::new([0.0, 0.0, 0.0]),
+                Vector3::new([1.0, 0.0, 0.0]),
+                Vector3::new([0.0, 1.0, 0.0]),
+                Vector3::new([0.0, 0.0, 1.0]),
+            ],
+            faces: vec![
+                ([(0, None, None), (1, None, None), (2, None, None)], None),
+                ([(1, None, None), (0, None, None), (3, None, None)], None),
+            ],
+            smoothing_groups: groups.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn normal(model: &Model, face: usize, corner: usize) -> [f32; 3] {
+        let n = model.get_normal(model.get_face(face).0[corner].2.unwrap());
+        [n.x(), n.y(), n.z()]
+    }
+
+    #[test]
+    fn test_flat() {
+        let mut model = hinge([None; 2]);
+        assert!(!model.has_normals());
+        model.generate_normals(NormalMode::Flat);
+        assert!(model.has_normals());
+        assert_eq!(normal(&model, 0, 0), [0.0, 0.0, 1.0]);
+        assert_eq!(normal(&model, 1, 0), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_smooth_shared_edge() {
+        let mut model = hinge([None; 2]);
+        model.generate_normals(NormalMode::Smooth {
+            weighting: NormalWeighting::Area,
+            crease_angle: None,
+        });
+        let n = normal(&model, 0, 0);
+        assert!((n[1] - FRAC_1_SQRT_2).abs() < 1e-6 && (n[2] - FRAC_1_SQRT_2).abs() < 1e-6);
+        // 共享的顶点去重后只有一个法向量
+        assert_eq!(model.get_face(0).0[0].2, model.get_face(1).0[1].2);
+        // 未共享的顶点只受自身面片影响
+        assert_eq!(normal(&model, 0, 2), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_crease_and_groups() {
+        let smooth = NormalMode::Smooth {
+            weighting: NormalWeighting::Angle,
+            crease_angle: Some(45f32.to_radians()),
+        };
+        let mut model = hinge([None; 2]);
+        model.generate_normals(smooth);
+        assert_eq!(normal(&model, 0, 0), [0.0, 0.0, 1.0]);
+
+        let mut model = hinge([Some(1), Some(2)]);
+        model.generate_normals(NormalMode::Smooth {
+            weighting: NormalWeighting::Angle,
+            crease_angle: None,
+        });
+        assert_eq!(normal(&model, 1, 1), [0.0, 1.0, 0.0]);
+    }
+}
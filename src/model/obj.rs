@@ -0,0 +1,829 @@
+use std::{
+    error::Error,
+    fmt, fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+};
+
+use crate::vec::{Vector2, Vector3};
+
+use super::{
+    mtl::{parse_mtl, write_mtl},
+    triangulate::triangulate,
+    FaceVertex, Model, SubMesh, SubMeshKind,
+};
+
+/// OBJ 解析错误的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjErrorKind {
+    /// 无法解析为浮点数
+    InvalidFloat,
+    /// 无法解析为索引
+    InvalidIndex,
+    /// 无法解析为整数
+    InvalidInteger,
+    /// 缺少必要的分量
+    MissingComponent,
+    /// 索引为0，OBJ的索引从1开始
+    ZeroIndex,
+    /// 索引超出了已定义元素的范围
+    IndexOutOfRange,
+    /// 引用的文件（如mtllib）无法读取
+    UnreadableFile,
+    /// 分量个数不符合任何一种写法
+    UnexpectedComponent,
+}
+
+impl fmt::Display for ObjErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::InvalidFloat => "invalid float",
+            Self::InvalidIndex => "invalid index",
+            Self::InvalidInteger => "invalid integer",
+            Self::MissingComponent => "missing component",
+            Self::ZeroIndex => "zero index",
+            Self::IndexOutOfRange => "index out of range",
+            Self::UnreadableFile => "unreadable file",
+            Self::UnexpectedComponent => "unexpected component",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 带有出错位置的OBJ解析错误
+#[derive(Debug, Clone)]
+pub struct ObjParseError {
+    pub file: PathBuf,
+    /// 行号，从1开始
+    pub line: usize,
+    /// 列号（按字符计），从1开始
+    pub column: usize,
+    /// 出错的token，缺少分量时为所在的整段内容
+    pub token: String,
+    pub kind: ObjErrorKind,
+}
+
+impl fmt::Display for ObjParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {} `{}`",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.kind,
+            self.token
+        )
+    }
+}
+
+impl Error for ObjParseError {}
+
+#[derive(Debug)]
+pub enum ObjError {
+    /// 读取文件失败
+    Io { file: PathBuf, source: io::Error },
+    /// 文件内容格式错误
+    Parse(ObjParseError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { file, source } => write!(f, "{}: {}", file.display(), source),
+            Self::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<ObjParseError> for ObjError {
+    fn from(e: ObjParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+/// OBJ加载选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObjLoadOptions {
+    /// 宽松模式：跳过格式错误的行并将错误作为警告收集，而不是直接返回错误
+    pub lenient: bool,
+}
+
+impl Model {
+    /// 以严格模式加载OBJ文件，遇到第一个格式错误即返回
+    pub fn load_from_obj(filename: impl AsRef<Path>) -> Result<Self, ObjError> {
+        Self::load_from_obj_with(filename, ObjLoadOptions::default()).map(|(model, _)| model)
+    }
+
+    /// 按给定选项加载OBJ文件，返回模型以及宽松模式下被跳过的行的警告
+    /// 文件按行流式读取，不会整个读入内存
+    pub fn load_from_obj_with(
+        filename: impl AsRef<Path>,
+        options: ObjLoadOptions,
+    ) -> Result<(Self, Vec<ObjParseError>), ObjError> {
+        let file = filename.as_ref();
+        let io_error = |source| ObjError::Io {
+            file: file.to_path_buf(),
+            source,
+        };
+        let f = fs::File::open(file).map_err(io_error)?;
+        let size = f.metadata().map_or(0, |m| m.len() as usize);
+        parse_obj(BufReader::new(f), file, options, size)
+    }
+}
+
+impl Model {
+    /// 保存为OBJ文件，有材质时在同一目录下写出同名的MTL文件
+    pub fn save_obj(&self, filename: impl AsRef<Path>) -> io::Result<()> {
+        let file = filename.as_ref();
+        let mtllib = if self.materials.is_empty() {
+            None
+        } else {
+            let mtl = file.with_extension("mtl");
+            let mut w = BufWriter::new(fs::File::create(&mtl)?);
+            write_mtl(
+                &self.materials,
+                &mut w,
+                file.parent().unwrap_or(Path::new("")),
+            )?;
+            w.flush()?;
+            mtl.file_name().map(|n| n.to_string_lossy().into_owned())
+        };
+        let mut w = BufWriter::new(fs::File::create(file)?);
+        self.write_obj(&mut w, mtllib.as_deref())?;
+        w.flush()
+    }
+
+    /// 写出OBJ文本，坐标、UV、法向量的序号以及面片顺序与模型中一一对应
+    /// OBJ无法在usemtl之后取消材质，之后未指定材质的面片写为`usemtl default`
+    /// 不写出部件，需要保留部件时使用二进制格式
+    pub fn write_obj(&self, w: &mut impl Write, mtllib: Option<&str>) -> io::Result<()> {
+        if let Some(mtllib) = mtllib {
+            writeln!(w, "mtllib {mtllib}")?;
+        }
+        for (i, v) in self.vertexs.iter().enumerate() {
+            write!(w, "v {} {} {}", v.x(), v.y(), v.z())?;
+            if let Some(c) = self.colors.get(i) {
+                write!(w, " {} {} {}", c.x(), c.y(), c.z())?;
+            }
+            writeln!(w)?;
+        }
+        for uv in &self.texture_vertexs {
+            writeln!(w, "vt {} {}", uv.x(), uv.y())?;
+        }
+        for n in &self.normals {
+            writeln!(w, "vn {} {} {}", n.x(), n.y(), n.z())?;
+        }
+
+        let (mut material, mut group) = (None, None);
+        for (i, &(face, m)) in self.faces.iter().enumerate() {
+            if m != material {
+                match m {
+                    Some(id) => writeln!(w, "usemtl {}", self.materials[id].name)?,
+                    None => writeln!(w, "usemtl default")?,
+                }
+                material = m;
+            }
+            let g = self.smoothing_group(i);
+            if g != group {
+                match g {
+                    Some(0) => writeln!(w, "s off")?,
+                    Some(g) => writeln!(w, "s {g}")?,
+                    // 之前的平滑组需要显式关闭
+                    None if group != Some(0) => writeln!(w, "s off")?,
+                    None => {}
+                }
+                group = g;
+            }
+            write!(w, "f")?;
+            for (v, uv, n) in face {
+                match (uv, n) {
+                    (Some(uv), Some(n)) => write!(w, " {}/{}/{}", v + 1, uv + 1, n + 1)?,
+                    (Some(uv), None) => write!(w, " {}/{}", v + 1, uv + 1)?,
+                    (None, Some(n)) => write!(w, " {}//{}", v + 1, n + 1)?,
+                    (None, None) => write!(w, " {}", v + 1)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+/// 逐行解析OBJ内容，`file`用于错误信息以及定位mtllib引用的文件
+/// `size_hint`为内容的字节数，用于预估各列表的容量
+fn parse_obj(
+    mut reader: impl BufRead,
+    file: &Path,
+    options: ObjLoadOptions,
+    size_hint: usize,
+) -> Result<(Model, Vec<ObjParseError>), ObjError> {
+    let mut parser = ObjParser {
+        model: Model::default(),
+        current_mtl_id: None,
+        current_smoothing_group: None,
+        dir: file.parent().unwrap_or(Path::new("")),
+        lenient: options.lenient,
+        warnings: Vec::new(),
+        face: Vec::new(),
+        polygon: Vec::new(),
+        object: None,
+        groups: Vec::new(),
+    };
+    // 按常见OBJ文件中每行的平均字节数估算，多分配的部分远小于反复扩容的开销
+    let model = &mut parser.model;
+    model.vertexs.reserve(size_hint / 128);
+    model.normals.reserve(size_hint / 128);
+    model.texture_vertexs.reserve(size_hint / 128);
+    model.faces.reserve(size_hint / 96);
+    model.smoothing_groups.reserve(size_hint / 96);
+
+    // 整个解析过程复用同一个行缓冲
+    let mut text = String::new();
+    let mut number = 0;
+    loop {
+        text.clear();
+        let n = reader.read_line(&mut text).map_err(|source| ObjError::Io {
+            file: file.to_path_buf(),
+            source,
+        })?;
+        if n == 0 {
+            break;
+        }
+        number += 1;
+        let line = Line {
+            file,
+            number,
+            text: text.trim_end_matches(['\n', '\r']),
+        };
+        if let Err(e) = parser.parse_line(&line) {
+            if options.lenient {
+                parser.warnings.push(e);
+            } else {
+                return Err(e.into());
+            }
+        }
+    }
+    parser.close_object();
+    parser.close_groups();
+    let model = &mut parser.model;
+    // 部分顶点带有颜色时，其余顶点补为白色
+    if !model.colors.is_empty() {
+        model
+            .colors
+            .resize(model.vertexs.len(), Vector3::new([1.0; 3]));
+    }
+    parser.model.sub_meshes.sort_by_key(|s| s.faces.start);
+    Ok((parser.model, parser.warnings))
+}
+
+struct ObjParser<'a> {
+    model: Model,
+    current_mtl_id: Option<usize>,
+    current_smoothing_group: Option<u32>,
+    /// OBJ文件所在目录，mtllib相对于该目录查找
+    dir: &'a Path,
+    lenient: bool,
+    warnings: Vec<ObjParseError>,
+    /// 面片顶点和多边形坐标的缓冲，在各行之间复用
+    face: Vec<FaceVertex>,
+    polygon: Vec<Vector3<f32>>,
+    /// 当前对象和当前组的名称及起始面片序号
+    object: Option<(String, usize)>,
+    groups: Vec<(String, usize)>,
+}
+
+/// 正在解析的一行，用于生成带位置的错误
+pub(super) struct Line<'a> {
+    pub(super) file: &'a Path,
+    pub(super) number: usize,
+    pub(super) text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    /// `token`必须是`self.text`的子串
+    pub(super) fn error(&self, token: &str, kind: ObjErrorKind) -> ObjParseError {
+        let offset = token.as_ptr() as usize - self.text.as_ptr() as usize;
+        ObjParseError {
+            file: self.file.to_path_buf(),
+            line: self.number,
+            column: self.text[..offset].chars().count() + 1,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    /// 行尾缺少分量的错误，token为整行内容
+    pub(super) fn missing(&self) -> ObjParseError {
+        ObjParseError {
+            file: self.file.to_path_buf(),
+            line: self.number,
+            column: self.text.trim_end().chars().count() + 1,
+            token: self.text.trim().to_string(),
+            kind: ObjErrorKind::MissingComponent,
+        }
+    }
+
+    pub(super) fn next_f32(&self, tokens: &mut SplitWhitespace<'a>) -> Result<f32, ObjParseError> {
+        let token = tokens.next().ok_or_else(|| self.missing())?;
+        token
+            .parse::<f32>()
+            .map_err(|_| self.error(token, ObjErrorKind::InvalidFloat))
+    }
+
+    pub(super) fn next_u32(&self, tokens: &mut SplitWhitespace<'a>) -> Result<u32, ObjParseError> {
+        let token = tokens.next().ok_or_else(|| self.missing())?;
+        token
+            .parse::<u32>()
+            .map_err(|_| self.error(token, ObjErrorKind::InvalidInteger))
+    }
+
+    /// 解析从1开始的索引并转换为从0开始，`count`为当前已定义的元素个数
+    /// 负数索引表示相对于当前已定义元素末尾的位置，-1即最后一个
+    fn index(&self, token: &str, count: usize) -> Result<usize, ObjParseError> {
+        let i = token
+            .parse::<isize>()
+            .map_err(|_| self.error(token, ObjErrorKind::InvalidIndex))?;
+        let i = match i {
+            0 => return Err(self.error(token, ObjErrorKind::ZeroIndex)),
+            i if i > 0 => i as usize - 1,
+            i => count.wrapping_sub(i.unsigned_abs()),
+        };
+        if i >= count {
+            return Err(self.error(token, ObjErrorKind::IndexOutOfRange));
+        }
+        Ok(i)
+    }
+
+    /// 解析面片顶点，支持`v`、`v/vt`、`v//vn`和`v/vt/vn`四种形式
+    fn face_vertex(&self, token: &str, model: &Model) -> Result<FaceVertex, ObjParseError> {
+        let mut iter = token.split('/');
+        let (vi, uvi, vni) = (iter.next(), iter.next(), iter.next());
+        if iter.next().is_some() {
+            return Err(self.error(token, ObjErrorKind::InvalidIndex));
+        }
+        let vi = match vi {
+            Some(vi) if !vi.is_empty() => self.index(vi, model.vertexs.len())?,
+            _ => return Err(self.error(token, ObjErrorKind::MissingComponent)),
+        };
+        let optional = |i: Option<&str>, count: usize| match i {
+            Some(i) if !i.is_empty() => self.index(i, count).map(Some),
+            _ => Ok(None),
+        };
+        Ok((
+            vi,
+            optional(uvi, model.texture_vertexs.len())?,
+            optional(vni, model.normals.len())?,
+        ))
+    }
+}
+
+impl<'a> ObjParser<'a> {
+    /// 结束当前部件，没有面片的部件不记录
+    fn close(&mut self, name: String, start: usize, kind: SubMeshKind) {
+        let end = self.model.faces.len();
+        if start < end {
+            self.model.sub_meshes.push(SubMesh {
+                name,
+                kind,
+                faces: start..end,
+            });
+        }
+    }
+
+    fn close_object(&mut self) {
+        if let Some((name, start)) = self.object.take() {
+            self.close(name, start, SubMeshKind::Object);
+        }
+    }
+
+    fn close_groups(&mut self) {
+        for (name, start) in std::mem::take(&mut self.groups) {
+            self.close(name, start, SubMeshKind::Group);
+        }
+    }
+
+    /// 解析一行，出错时不会修改模型
+    fn parse_line(&mut self, line: &Line) -> Result<(), ObjParseError> {
+        let mut tokens = line.text.split_whitespace();
+        let first_flag = tokens.next();
+        match first_flag {
+            Some("mtllib") => {
+                let names = tokens.collect::<Vec<_>>();
+                if names.is_empty() {
+                    return Err(line.missing());
+                }
+                // 所有文件都解析成功后才加入模型
+                let mut loaded = Vec::with_capacity(names.len());
+                for name in names {
+                    let file = self.dir.join(name);
+                    let s = fs::read_to_string(&file)
+                        .map_err(|_| line.error(name, ObjErrorKind::UnreadableFile))?;
+                    let materials = parse_mtl(&s, &file, self.lenient, &mut self.warnings)?;
+                    loaded.push((file, materials));
+                }
+                for (file, materials) in loaded {
+                    for m in materials {
+                        self.model.insert_material(m);
+                    }
+                    self.model.dependencies.push(file);
+                }
+            }
+            Some("usemtl") => {
+                let mtl = tokens.next().ok_or_else(|| line.missing())?;
+                self.current_mtl_id = Some(self.model.material_id_or_insert(mtl));
+            }
+            // 对象名可以包含空格
+            Some("o") => {
+                let name = line.text.trim_start()[1..].trim();
+                if name.is_empty() {
+                    return Err(line.missing());
+                }
+                self.close_object();
+                self.object = Some((name.to_string(), self.model.faces.len()));
+            }
+            // 一行可以指定多个组名，面片同时属于这些组，不指定时为default组
+            Some("g") => {
+                self.close_groups();
+                let start = self.model.faces.len();
+                self.groups = tokens.map(|name| (name.to_string(), start)).collect();
+                if self.groups.is_empty() {
+                    self.groups.push(("default".to_string(), start));
+                }
+            }
+            // 平滑组，off和0都表示关闭平滑
+            Some("s") => {
+                let group = match tokens.clone().next() {
+                    Some("off") => 0,
+                    _ => line.next_u32(&mut tokens)?,
+                };
+                self.current_smoothing_group = Some(group);
+            }
+            // 顶点和法向量解析
+            Some("v") | Some("vn") => {
+                let x = line.next_f32(&mut tokens)?;
+                let y = line.next_f32(&mut tokens)?;
+                let z = line.next_f32(&mut tokens)?;
+                let tuple = Vector3::new([x, y, z]);
+                match first_flag {
+                    Some("v") => {
+                        // `v x y z w`的w只用于有理曲线，检查格式后忽略；
+                        // 扩展格式`v x y z r g b`，颜色取值范围0~1
+                        let color = match tokens.clone().count() {
+                            0 => None,
+                            1 => {
+                                line.next_f32(&mut tokens)?;
+                                None
+                            }
+                            3 => {
+                                let r = line.next_f32(&mut tokens)?;
+                                let g = line.next_f32(&mut tokens)?;
+                                let b = line.next_f32(&mut tokens)?;
+                                Some(Vector3::new([r, g, b]))
+                            }
+                            _ => {
+                                let token = tokens.next().unwrap_or_default();
+                                return Err(line.error(token, ObjErrorKind::UnexpectedComponent));
+                            }
+                        };
+                        let model = &mut self.model;
+                        model.vertexs.push(tuple);
+                        if let Some(color) = color {
+                            // 之前没有颜色的顶点补为白色
+                            model
+                                .colors
+                                .resize(model.vertexs.len() - 1, Vector3::new([1.0; 3]));
+                            model.colors.push(color);
+                        }
+                    }
+                    Some("vn") => self.model.normals.push(tuple),
+                    _ => unreachable!(),
+                }
+            }
+            // uv坐标解析
+            Some("vt") => {
+                let x = line.next_f32(&mut tokens)?;
+                let y = line.next_f32(&mut tokens)?;
+                self.model.texture_vertexs.push(Vector2::new([x, y]));
+            }
+            // 面片解析
+            Some("f") => {
+                self.face.clear();
+                for token in tokens {
+                    let v = line.face_vertex(token, &self.model)?;
+                    self.face.push(v);
+                }
+                let face = &self.face;
+                if face.len() < 3 {
+                    return Err(line.missing());
+                }
+                let model = &mut self.model;
+                if let [a, b, c] = face[..] {
+                    model.faces.push(([a, b, c], self.current_mtl_id));
+                    model.smoothing_groups.push(self.current_smoothing_group);
+                    return Ok(());
+                }
+                self.polygon.clear();
+                self.polygon.extend(face.iter().map(|c| model.vertexs[c.0]));
+                for [a, b, c] in triangulate(&self.polygon) {
+                    model
+                        .faces
+                        .push(([face[a], face[b], face[c]], self.current_mtl_id));
+                    model.smoothing_groups.push(self.current_smoothing_group);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str, lenient: bool) -> Result<(Model, Vec<ObjParseError>), ObjParseError> {
+        let options = ObjLoadOptions { lenient };
+        parse_obj(s.as_bytes(), Path::new("test.obj"), options, s.len()).map_err(|e| match e {
+            ObjError::Parse(e) => e,
+            ObjError::Io { source, .. } => panic!("{source}"),
+        })
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\n";
+
+    #[test]
+    fn test_parse_triangle() {
+        let s = format!("{TRIANGLE}f 1/1/1 2/1/1 3/1/1\n");
+        let (model, warnings) = parse(&s, false).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(model.faces_count(), 1);
+        assert_eq!(
+            model.get_face(0).0,
+            [
+                (0, Some(0), Some(0)),
+                (1, Some(0), Some(0)),
+                (2, Some(0), Some(0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_obj() {
+        let dir = std::env::temp_dir().join(format!("tinyrenderer-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let s = format!("{TRIANGLE}usemtl a\nf 1 2 3\n");
+        let (mut model, _) = parse(&s, false).unwrap();
+        model.material_mut(0).diffuse_map = Some(dir.join("tex/a.png"));
+        // 同目录下写出同名的MTL，贴图路径相对于模型所在目录
+        model.save(dir.join("a.obj")).unwrap();
+        let mtl = fs::read_to_string(dir.join("a.mtl")).unwrap();
+        assert!(mtl.contains("map_Kd tex/a.png"));
+        let copy = Model::load(dir.join("a.obj")).unwrap();
+        assert_eq!(copy.faces, model.faces);
+        assert_eq!(
+            copy.get_material(0).diffuse_map,
+            Some(dir.join("tex/a.png"))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let model = Model::load_from_obj("assets/african_head.obj").unwrap();
+        let mut buf = Vec::new();
+        model.write_obj(&mut buf, None).unwrap();
+        let (copy, _) = parse(std::str::from_utf8(&buf).unwrap(), false).unwrap();
+        assert_eq!(copy.vertexs, model.vertexs);
+        assert_eq!(copy.texture_vertexs, model.texture_vertexs);
+        assert_eq!(copy.normals, model.normals);
+        assert_eq!(copy.faces, model.faces);
+
+        let s = format!("{TRIANGLE}f 1 2 3\nusemtl a\ns 1\nf 1//1 2//1 3//1\n");
+        let (model, _) = parse(&s, false).unwrap();
+        let mut buf = Vec::new();
+        model.write_obj(&mut buf, None).unwrap();
+        let (copy, _) = parse(std::str::from_utf8(&buf).unwrap(), false).unwrap();
+        assert_eq!(copy.faces, model.faces);
+        assert_eq!(copy.smoothing_groups, model.smoothing_groups);
+
+        // 有材质、平滑组的面片之后又出现没有的面片时保持原有顺序
+        let s = format!("{TRIANGLE}usemtl a\ns 1\nf 1 2 3\nf 2 3 1\nf 3 1 2\n");
+        let (mut model, _) = parse(&s, false).unwrap();
+        model.faces[0].1 = None;
+        model.smoothing_groups[0] = None;
+        model.faces[2].1 = None;
+        model.smoothing_groups[2] = None;
+        let mut buf = Vec::new();
+        model.write_obj(&mut buf, None).unwrap();
+        let (copy, _) = parse(std::str::from_utf8(&buf).unwrap(), false).unwrap();
+        let vertices = |m: &Model| m.faces.iter().map(|f| f.0[0].0).collect::<Vec<_>>();
+        assert_eq!(vertices(&copy), [0, 1, 2]);
+        assert_eq!(copy.get_face(1).1, copy.find_material("a"));
+        assert_ne!(copy.get_face(2).1, copy.find_material("a"));
+        assert_eq!(
+            (0..3).map(|i| copy.smoothing_group(i)).collect::<Vec<_>>(),
+            [None, Some(1), Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_triangulate_polygons() {
+        let s = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 2 0\nvt 0 0\nvn 0 0 1\n\
+                 f 1/1/1 2/1/1 3/1/1 4/1/1\nf 1/1/1 2/1/1 3/1/1 5/1/1 4/1/1\n";
+        let (model, _) = parse(s, false).unwrap();
+        assert_eq!(model.faces_count(), 5);
+        assert_eq!(model.get_face(1).0.map(|v| v.0), [0, 2, 3]);
+    }
+
+    #[test]
+    fn test_face_index_forms() {
+        let s =
+            format!("{TRIANGLE}f 1 2 3\nf 1/1 2/1 3/1\nf 1//1 2//1 3//1\nf -3/-1/-1 -2//-1 -1\n");
+        let (model, _) = parse(&s, false).unwrap();
+        let faces = (0..4).map(|i| model.get_face(i).0[0]).collect::<Vec<_>>();
+        assert_eq!(
+            faces,
+            [
+                (0, None, None),
+                (0, Some(0), None),
+                (0, None, Some(0)),
+                (0, Some(0), Some(0))
+            ]
+        );
+        assert_eq!(model.get_face(3).0[1], (1, None, Some(0)));
+        assert_eq!(model.get_face(3).0[2], (2, None, None));
+    }
+
+    #[test]
+    fn test_repeated_usemtl() {
+        let s =
+            format!("{TRIANGLE}f 1 2 3\nusemtl a\nf 1 2 3\nusemtl b\nf 1 2 3\nusemtl a\nf 1 2 3\n");
+        let (model, _) = parse(&s, false).unwrap();
+        assert_eq!(model.materials().len(), 2);
+        assert_eq!(model.find_material("a"), Some(0));
+        assert_eq!(model.get_face(3).1, Some(0));
+        let batches = model
+            .material_batches()
+            .into_iter()
+            .map(|b| (b.material, b.faces))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            batches,
+            [(None, vec![0]), (Some(0), vec![1, 3]), (Some(1), vec![2])]
+        );
+    }
+
+    #[test]
+    fn test_vertex_colors() {
+        let s = "v 0 0 0\nv 1 0 0 1 0 0\nv 0 1 0 1\nv 1 1 0 0 0.5 1\nf 1 2 3\n";
+        let (model, _) = parse(s, false).unwrap();
+        assert!(model.has_vertex_colors());
+        let colors = (0..4)
+            .map(|i| model.get_vertex_color(i).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            colors,
+            [
+                [1.0, 1.0, 1.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 1.0],
+                [0.0, 0.5, 1.0]
+            ]
+            .map(Vector3::new)
+        );
+
+        let mut buf = Vec::new();
+        model.write_obj(&mut buf, None).unwrap();
+        let (copy, _) = parse(std::str::from_utf8(&buf).unwrap(), false).unwrap();
+        assert_eq!(copy.colors, model.colors);
+
+        let e = parse("v 0 0 0 1 x 0\n", false).unwrap_err();
+        assert_eq!((e.kind, e.column), (ObjErrorKind::InvalidFloat, 11));
+        assert!(!parse(TRIANGLE, false).unwrap().0.has_vertex_colors());
+        // 只有w或rgb两种扩展写法，其余个数报错
+        for s in ["v 0 0 0 1 2\n", "v 0 0 0 1 2 3 4\n"] {
+            let e = parse(s, false).unwrap_err();
+            assert_eq!((e.kind, e.column), (ObjErrorKind::UnexpectedComponent, 9));
+        }
+        let e = parse("v 0 0 0 w\n", false).unwrap_err();
+        assert_eq!(e.kind, ObjErrorKind::InvalidFloat);
+    }
+
+    #[test]
+    fn test_failed_mtllib() {
+        let dir = std::env::temp_dir().join(format!("tinyrenderer-mtllib-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mtl"), "newmtl a\n").unwrap();
+        // 后一个文件读取失败时，前一个文件的材质也不加入模型
+        let s = "mtllib a.mtl b.mtl\n";
+        let options = ObjLoadOptions { lenient: true };
+        let (model, warnings) = parse_obj(s.as_bytes(), &dir.join("test.obj"), options, 0).unwrap();
+        assert_eq!(warnings[0].kind, ObjErrorKind::UnreadableFile);
+        assert!(model.materials().is_empty());
+        assert!(model.dependencies.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_smoothing_groups() {
+        let s = format!("{TRIANGLE}f 1 2 3\ns 1\nf 1 2 3\ns off\nf 1 2 3\n");
+        let (model, _) = parse(&s, false).unwrap();
+        assert_eq!(
+            (0..3).map(|i| model.smoothing_group(i)).collect::<Vec<_>>(),
+            [None, Some(1), Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_objects_and_groups() {
+        let s = format!(
+            "{TRIANGLE}f 1 2 3\no 芙宁娜 body\ng hair front\nf 1 2 3\nf 1 2 3\n\
+             g\nf 1 2 3\ng empty\no hat\ng hair\nf 1 2 3\n"
+        );
+        let (model, _) = parse(&s, false).unwrap();
+        let ranges = |name| {
+            model
+                .sub_meshes_named(name)
+                .map(|s| (s.kind, s.faces.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges("芙宁娜 body"), [(SubMeshKind::Object, 1..4)]);
+        assert_eq!(
+            ranges("hair"),
+            [(SubMeshKind::Group, 1..3), (SubMeshKind::Group, 4..5)]
+        );
+        assert_eq!(ranges("front"), [(SubMeshKind::Group, 1..3)]);
+        assert_eq!(ranges("default"), [(SubMeshKind::Group, 3..4)]);
+        assert!(ranges("empty").is_empty());
+        assert_eq!(model.faces_where(|s| s.name == "hair"), [1, 2, 4]);
+        assert_eq!(model.material_batches_of([1, 4])[0].faces, [1, 4]);
+    }
+
+    #[test]
+    fn test_invalid_float_position() {
+        let e = parse("v 0 0 0\nv 1 x2 0\n", false).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.kind),
+            (2, 5, ObjErrorKind::InvalidFloat)
+        );
+        assert_eq!(e.token, "x2");
+        assert_eq!(e.to_string(), "test.obj:2:5: invalid float `x2`");
+    }
+
+    #[test]
+    fn test_missing_component() {
+        let e = parse("vt 0.5\n", false).unwrap_err();
+        assert_eq!(
+            (e.line, e.column, e.kind),
+            (1, 7, ObjErrorKind::MissingComponent)
+        );
+    }
+
+    #[test]
+    fn test_bad_indices() {
+        let e = parse(&format!("{TRIANGLE}f 1/1/1 2/1/1 0/1/1\n"), false).unwrap_err();
+        assert_eq!((e.line, e.column, e.kind), (6, 15, ObjErrorKind::ZeroIndex));
+
+        let e = parse(&format!("{TRIANGLE}f 1/1/1 2/1/1 3/2/1\n"), false).unwrap_err();
+        assert_eq!((e.column, e.kind), (17, ObjErrorKind::IndexOutOfRange));
+        assert_eq!(e.token, "2");
+
+        let e = parse(&format!("{TRIANGLE}f 1/1/1 2/1/1 3/1/a\n"), false).unwrap_err();
+        assert_eq!((e.column, e.kind), (19, ObjErrorKind::InvalidIndex));
+
+        let e = parse(&format!("{TRIANGLE}f 1 2 -4\n"), false).unwrap_err();
+        assert_eq!((e.column, e.kind), (7, ObjErrorKind::IndexOutOfRange));
+
+        let e = parse(&format!("{TRIANGLE}f 1 2 /1/1\n"), false).unwrap_err();
+        assert_eq!((e.column, e.kind), (7, ObjErrorKind::MissingComponent));
+    }
+
+    #[test]
+    fn test_lenient_skips_bad_lines() {
+        let s = format!("{TRIANGLE}v 1 1 nan?\nf 1/1/1 2/1/1 3/1/1\nf 1/1/1 2/1/1 9/1/1\n");
+        let (model, warnings) = parse(&s, true).unwrap();
+        assert_eq!(model.vertexs_count(), 3);
+        assert_eq!(model.faces_count(), 1);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.line, w.kind))
+                .collect::<Vec<_>>(),
+            [
+                (6, ObjErrorKind::InvalidFloat),
+                (8, ObjErrorKind::IndexOutOfRange)
+            ]
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use std::{error::Error, fmt, fs, io, path::Path, str::SplitAsciiWhitespace};
+
+use crate::vec::{Vector2, Vector3};
+
+use super::{triangulate::triangulate, Model};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    /// 文件头第line行无法解析
+    InvalidHeader {
+        line: usize,
+        text: String,
+    },
+    /// vertex或face缺少必需的属性
+    MissingProperty(&'static str),
+    /// 读取element的第index项时数据提前结束
+    UnexpectedEof {
+        element: String,
+        index: usize,
+    },
+    /// element的第index项中有非法的值
+    InvalidValue {
+        element: String,
+        index: usize,
+    },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::InvalidHeader { line, text } => write!(f, "{line}: invalid ply header `{text}`"),
+            Self::MissingProperty(name) => write!(f, "missing ply property `{name}`"),
+            Self::UnexpectedEof { element, index } => {
+                write!(f, "unexpected end of file in {element} {index}")
+            }
+            Self::InvalidValue { element, index } => {
+                write!(f, "invalid value in {element} {index}")
+            }
+        }
+    }
+}
+
+impl Error for PlyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Model {
+    /// 加载ASCII或二进制PLY，读取顶点坐标、法向量、颜色、UV和多边形面片
+    /// 其他element会被跳过
+    pub fn load_from_ply(filename: impl AsRef<Path>) -> Result<Self, PlyError> {
+        parse_ply(&fs::read(filename)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// 颜色分量归一化到0~1时的除数
+    fn color_scale(self) -> f32 {
+        match self {
+            Self::U16 => 65535.0,
+            Self::F32 | Self::F64 => 1.0,
+            _ => 255.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(Scalar),
+    /// (元素个数的类型, 元素的类型)
+    List(Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, Property)>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|(name, _)| names.contains(&name.as_str()))
+    }
+
+    fn scalar_type(&self, index: usize) -> Scalar {
+        match self.properties[index].1 {
+            Property::Scalar(ty) | Property::List(_, ty) => ty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// 解析文件头，返回数据格式、element列表和数据部分的起始偏移
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), PlyError> {
+    let mut format = None;
+    let mut elements = Vec::<Element>::new();
+    let mut offset = 0;
+    for (i, raw) in data.split(|&b| b == b'\n').enumerate() {
+        offset += raw.len() + 1;
+        let text = String::from_utf8_lossy(raw);
+        let invalid = || PlyError::InvalidHeader {
+            line: i + 1,
+            text: text.trim().to_string(),
+        };
+        let tokens = text.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["ply"] if i == 0 => {}
+            _ if i == 0 => return Err(invalid()),
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid()),
+                })
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid())?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let property = Property::List(
+                    Scalar::parse(count).ok_or_else(invalid)?,
+                    Scalar::parse(item).ok_or_else(invalid)?,
+                );
+                let element = elements.last_mut().ok_or_else(invalid)?;
+                element.properties.push((name.to_string(), property));
+            }
+            ["property", ty, name] => {
+                let property = Property::Scalar(Scalar::parse(ty).ok_or_else(invalid)?);
+                let element = elements.last_mut().ok_or_else(invalid)?;
+                element.properties.push((name.to_string(), property));
+            }
+            ["end_header"] => {
+                let format = format.ok_or_else(invalid)?;
+                return Ok((format, elements, offset.min(data.len())));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Err(PlyError::InvalidHeader {
+        line: 0,
+        text: "missing end_header".to_string(),
+    })
+}
+
+enum ReadError {
+    Eof,
+    Invalid,
+}
+
+/// 数据部分的读取器
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, ReadError> {
+        match self {
+            Self::Ascii(tokens) => {
+                let token = tokens.next().ok_or(ReadError::Eof)?;
+                token.parse().map_err(|_| ReadError::Invalid)
+            }
+            Self::Binary {
+                data,
+                pos,
+                big_endian,
+            } => {
+                let bytes = data.get(*pos..*pos + ty.size()).ok_or(ReadError::Eof)?;
+                *pos += ty.size();
+                macro_rules! num {
+                    ($t:ty) => {{
+                        let b = bytes.try_into().unwrap();
+                        (if *big_endian {
+                            <$t>::from_be_bytes(b)
+                        } else {
+                            <$t>::from_le_bytes(b)
+                        }) as f64
+                    }};
+                }
+                Ok(match ty {
+                    Scalar::I8 => num!(i8),
+                    Scalar::U8 => num!(u8),
+                    Scalar::I16 => num!(i16),
+                    Scalar::U16 => num!(u16),
+                    Scalar::I32 => num!(i32),
+                    Scalar::U32 => num!(u32),
+                    Scalar::F32 => num!(f32),
+                    Scalar::F64 => num!(f64),
+                })
+            }
+        }
+    }
+
+    /// 读取一项数据，每个属性的值放入`record`中对应的位置，标量属性只有一个值
+    fn record(&mut self, element: &Element, record: &mut [Vec<f64>]) -> Result<(), ReadError> {
+        for ((_, property), values) in element.properties.iter().zip(record) {
+            values.clear();
+            match *property {
+                Property::Scalar(ty) => values.push(self.read(ty)?),
+                Property::List(count, ty) => {
+                    let n = self.read(count)?;
+                    if n < 0.0 {
+                        return Err(ReadError::Invalid);
+                    }
+                    for _ in 0..n as usize {
+                        values.push(self.read(ty)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_ply(data: &[u8]) -> Result<Model, PlyError> {
+    let (format, elements, offset) = parse_header(data)?;
+    let body = &data[offset..];
+    let text;
+    let mut body = match format {
+        Format::Ascii => {
+            text = String::from_utf8_lossy(body);
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        _ => Body::Binary {
+            data: body,
+            pos: 0,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut model = Model::default();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    for element in &elements {
+        let mut record = vec![Vec::new(); element.properties.len()];
+        match element.name.as_str() {
+            "vertex" => {
+                let required = |name| element.find(&[name]).ok_or(PlyError::MissingProperty(name));
+                let position = [required("x")?, required("y")?, required("z")?];
+                let normal = [["nx"], ["ny"], ["nz"]].map(|n| element.find(&n));
+                let color = [
+                    ["red", "r", "diffuse_red"],
+                    ["green", "g", "diffuse_green"],
+                    ["blue", "b", "diffuse_blue"],
+                ]
+                .map(|n| element.find(&n));
+                let uv = [
+                    ["s", "u", "texture_u", "texture_s"],
+                    ["t", "v", "texture_v", "texture_t"],
+                ]
+                .map(|n| element.find(&n));
+                let normal = normal
+                    .iter()
+                    .all(Option::is_some)
+                    .then(|| normal.map(Option::unwrap));
+                let color = color
+                    .iter()
+                    .all(Option::is_some)
+                    .then(|| color.map(Option::unwrap));
+                let uv = uv
+                    .iter()
+                    .all(Option::is_some)
+                    .then(|| uv.map(Option::unwrap));
+                let color_scale = color.map(|c| c.map(|i| element.scalar_type(i).color_scale()));
+                has_normals = normal.is_some();
+                has_uvs = uv.is_some();
+
+                model.vertexs.reserve(element.count.min(1 << 20));
+                for index in 0..element.count {
+                    read_record(&mut body, element, &mut record, index)?;
+                    let value = |i: usize| record[i].first().copied().unwrap_or(0.0) as f32;
+                    model.vertexs.push(Vector3::new(position.map(value)));
+                    if let Some(n) = normal {
+                        model.normals.push(Vector3::new(n.map(value)));
+                    }
+                    if let (Some(c), Some(scale)) = (color, color_scale) {
+                        let rgb = [0, 1, 2].map(|k| value(c[k]) / scale[k]);
+                        model.colors.push(Vector3::new(rgb));
+                    }
+                    if let Some(uv) = uv {
+                        model.texture_vertexs.push(Vector2::new(uv.map(value)));
+                    }
+                }
+            }
+            "face" => {
+                let indices = element
+                    .find(&["vertex_indices", "vertex_index"])
+                    .ok_or(PlyError::MissingProperty("vertex_indices"))?;
+                // 按面片给出的UV，每个角两个值
+                let texcoord = element.find(&["texcoord"]);
+                for index in 0..element.count {
+                    read_record(&mut body, element, &mut record, index)?;
+                    let polygon = record[indices]
+                        .iter()
+                        .map(|&i| {
+                            // 负数、小数直接转换会被截断成另一个合法的序号
+                            if i < 0.0 || i.fract() != 0.0 {
+                                return None;
+                            }
+                            let i = i as usize;
+                            (i < model.vertexs.len()).then_some(i)
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| PlyError::InvalidValue {
+                            element: element.name.clone(),
+                            index,
+                        })?;
+                    let uvs = texcoord
+                        .map(|t| &record[t])
+                        .filter(|uvs| uvs.len() == polygon.len() * 2)
+                        .map(|uvs| {
+                            let base = model.texture_vertexs.len();
+                            model.texture_vertexs.extend(
+                                uvs.chunks_exact(2)
+                                    .map(|uv| Vector2::new([uv[0] as f32, uv[1] as f32])),
+                            );
+                            base
+                        });
+                    let points = polygon
+                        .iter()
+                        .map(|&i| model.vertexs[i])
+                        .collect::<Vec<_>>();
+                    for t in triangulate(&points) {
+                        let face = t.map(|k| {
+                            let v = polygon[k];
+                            let uv = match uvs {
+                                Some(base) => Some(base + k),
+                                None => has_uvs.then_some(v),
+                            };
+                            (v, uv, has_normals.then_some(v))
+                        });
+                        model.faces.push((face, None));
+                    }
+                }
+            }
+            _ => {
+                for index in 0..element.count {
+                    read_record(&mut body, element, &mut record, index)?;
+                }
+            }
+        }
+    }
+    model.smoothing_groups = vec![None; model.faces.len()];
+    Ok(model)
+}
+
+fn read_record(
+    body: &mut Body,
+    element: &Element,
+    record: &mut [Vec<f64>],
+    index: usize,
+) -> Result<(), PlyError> {
+    body.record(element, record).map_err(|e| match e {
+        ReadError::Eof => PlyError::UnexpectedEof {
+            element: element.name.clone(),
+            index,
+        },
+        ReadError::Invalid => PlyError::InvalidValue {
+            element: element.name.clone(),
+            index,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_ply() {
+        let s = "ply\n\
+                 format ascii 1.0\n\
+                 comment made by hand\n\
+                 element vertex 4\n\
+                 property float x\n\
+                 property float y\n\
+                 property float z\n\
+                 property uchar red\n\
+                 property uchar green\n\
+                 property uchar blue\n\
+                 element face 1\n\
+                 property list uchar int vertex_indices\n\
+                 end_header\n\
+                 0 0 0 255 0 0\n\
+                 1 0 0 0 255 0\n\
+                 1 1 0 0 0 255\n\
+                 0 1 0 255 255 255\n\
+                 4 0 1 2 3\n";
+        let model = parse_ply(s.as_bytes()).unwrap();
+        assert_eq!((model.vertexs_count(), model.faces_count()), (4, 2));
+        assert!(model.has_vertex_colors());
+        let c = model.get_vertex_color(1).unwrap();
+        assert_eq!([c.x(), c.y(), c.z()], [0.0, 1.0, 0.0]);
+        assert_eq!(model.get_face(1).0[2], (3, None, None));
+
+        let e = parse_ply(s.replace("4 0 1 2 3", "3 0 1 9").as_bytes()).unwrap_err();
+        assert!(matches!(e, PlyError::InvalidValue { index: 0, .. }));
+        // 负数、小数序号不能截断成合法序号
+        for polygon in ["3 0 1 -1", "3 0 1 1.5"] {
+            let e = parse_ply(s.replace("4 0 1 2 3", polygon).as_bytes()).unwrap_err();
+            assert!(matches!(e, PlyError::InvalidValue { index: 0, .. }));
+        }
+        // 元素个数远超实际数据时报错，而不是预先分配巨大的内存
+        assert!(parse_ply(s.replace("vertex 4", "vertex 4000000000").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_binary_ply() {
+        let mut data = b"ply\r\n\
+                         format binary_big_endian 1.0\r\n\
+                         element vertex 3\r\n\
+                         property float x\r\n\
+                         property float y\r\n\
+                         property float z\r\n\
+                         property float nx\r\n\
+                         property float ny\r\n\
+                         property float nz\r\n\
+                         element face 1\r\n\
+                         property list uchar uint vertex_index\r\n\
+                         end_header\r\n"
+            .to_vec();
+        for p in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for f in p.iter().chain(&[0.0, 0.0, 1.0]) {
+                data.extend(f.to_be_bytes());
+            }
+        }
+        data.push(3);
+        for i in [0u32, 1, 2] {
+            data.extend(i.to_be_bytes());
+        }
+        let model = parse_ply(&data).unwrap();
+        assert_eq!(model.faces_count(), 1);
+        assert_eq!(model.get_vertex(1).x(), 1.0);
+        assert_eq!(model.get_normal(2).z(), 1.0);
+        assert_eq!(model.get_face(0).0[1], (1, None, Some(1)));
+
+        let e = parse_ply(&data[..data.len() - 2]).unwrap_err();
+        assert!(matches!(e, PlyError::UnexpectedEof { .. }));
+    }
+}
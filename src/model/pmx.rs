@@ -0,0 +1,473 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use crate::vec::{Vector2, Vector3, Vector4};
+
+use super::{AlphaMode, Material, Model};
+
+#[derive(Debug)]
+pub enum PmxError {
+    Io(io::Error),
+    /// 文件头不是`PMX `
+    InvalidMagic,
+    /// 不支持的版本，仅支持2.0和2.1
+    UnsupportedVersion(f32),
+    /// 数据在指定偏移处提前结束
+    UnexpectedEof {
+        offset: usize,
+    },
+    /// 指定偏移处的字段取值非法
+    InvalidValue {
+        what: &'static str,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for PmxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::InvalidMagic => write!(f, "not a pmx file"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported pmx version {v}"),
+            Self::UnexpectedEof { offset } => write!(f, "unexpected end of file at {offset:#x}"),
+            Self::InvalidValue { what, offset } => write!(f, "invalid {what} at {offset:#x}"),
+        }
+    }
+}
+
+impl Error for PmxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PmxError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Model {
+    /// 加载PMX文件中的网格和材质，贴图路径相对于PMX文件所在目录解析
+    /// 蒙皮权重、描边、球面贴图、卡通渲染贴图以及之后的骨骼、表情等数据渲染时用不到，不读取
+    pub fn load_from_pmx(filename: impl AsRef<Path>) -> Result<Self, PmxError> {
+        let filename = filename.as_ref();
+        let data = fs::read(filename)?;
+        parse_pmx(&data, filename.parent().unwrap_or(Path::new("")))
+    }
+}
+
+fn parse_pmx(data: &[u8], dir: &Path) -> Result<Model, PmxError> {
+    let mut r = Reader::new(data);
+    if r.bytes(4)? != b"PMX " {
+        return Err(PmxError::InvalidMagic);
+    }
+    let version = r.f32()?;
+    if version != 2.0 && version != 2.1 {
+        return Err(PmxError::UnsupportedVersion(version));
+    }
+    let globals_offset = r.pos;
+    let globals = r.u8()? as usize;
+    if globals < 8 {
+        return Err(r.invalid("globals count", globals_offset));
+    }
+    let globals = r.bytes(globals)?.to_vec();
+    r.utf8 = match globals[0] {
+        0 => false,
+        1 => true,
+        _ => return Err(r.invalid("text encoding", globals_offset + 1)),
+    };
+    let additional_uv_count = globals[1] as usize;
+    if additional_uv_count > 4 {
+        return Err(r.invalid("additional uv count", globals_offset + 2));
+    }
+    r.index_sizes.copy_from_slice(&globals[2..8]);
+    for (i, size) in r.index_sizes.iter().enumerate() {
+        if ![1, 2, 4].contains(size) {
+            return Err(r.invalid("index size", globals_offset + 3 + i));
+        }
+    }
+
+    // 模型名称、注释
+    for _ in 0..4 {
+        r.text()?;
+    }
+
+    let mut model = Model::default();
+
+    // 顶点
+    let vertex_count = r.count()?;
+    for _ in 0..vertex_count {
+        model.vertexs.push(r.vec3()?);
+        model.normals.push(r.vec3()?);
+        // PMX的UV原点在左上角，转换为OBJ的左下角约定
+        let uv = r.vec2()?;
+        model
+            .texture_vertexs
+            .push(Vector2::new([uv.x(), 1.0 - uv.y()]));
+        // 追加UV、蒙皮权重、描边宽度缩放
+        r.bytes(additional_uv_count * 16)?;
+        r.skip_deform(version)?;
+        r.f32()?;
+    }
+
+    // 面片，每3个顶点序号组成一个三角形
+    let index_count = r.count()?;
+    // 个数来自文件，预分配设上限，数据不足时在读取中途报错
+    let mut triangles = Vec::with_capacity((index_count / 3).min(1 << 20));
+    for _ in 0..index_count / 3 {
+        let offset = r.pos;
+        let t = [r.vertex_index()?, r.vertex_index()?, r.vertex_index()?];
+        if t.iter().any(|&i| i >= vertex_count) {
+            return Err(r.invalid("vertex index", offset));
+        }
+        triangles.push(t);
+    }
+    for _ in 0..index_count % 3 {
+        r.vertex_index()?;
+    }
+
+    // 贴图路径
+    let textures = (0..r.count()?)
+        .map(|_| Ok(dir.join(r.text()?.replace('\\', "/"))))
+        .collect::<Result<Vec<_>, PmxError>>()?;
+    let texture = |i: Option<usize>| i.and_then(|i| textures.get(i).cloned());
+
+    // 材质，按顺序各自占用若干个三角形
+    let mut next_face = 0;
+    for _ in 0..r.count()? {
+        let name = r.text()?;
+        // 英文名称
+        r.text()?;
+        let diffuse = r.vec4()?;
+        let specular = r.vec3()?;
+        let specular_strength = r.f32()?;
+        let ambient = r.vec3()?;
+        // 绘制标志、描边颜色和宽度
+        r.bytes(1 + 16 + 4)?;
+        let diffuse_map = texture(r.index(TEXTURE)?);
+        // 球面贴图及其混合方式
+        r.index(TEXTURE)?;
+        let offset = r.pos;
+        if r.u8()? > 3 {
+            return Err(r.invalid("sphere mode", offset));
+        }
+        // 卡通渲染贴图，0为贴图序号，1为共享的toon01.bmp~toon10.bmp
+        let offset = r.pos;
+        match r.u8()? {
+            0 => {
+                r.index(TEXTURE)?;
+            }
+            1 => {
+                r.u8()?;
+            }
+            _ => return Err(r.invalid("toon reference", offset)),
+        }
+        // 备注
+        r.text()?;
+        let offset = r.pos;
+        let face_count = r.count()? / 3;
+        if next_face + face_count > triangles.len() {
+            return Err(r.invalid("material surface count", offset));
+        }
+
+        // PMX中材质可以重名，模型的材质表按名称去重，需要保证名称唯一
+        let mut material = Material::new(&model.unique_material_name(&name));
+        material.diffuse = Vector3::new([diffuse.x(), diffuse.y(), diffuse.z()]);
+        material.dissolve = diffuse.w();
+        // PMX没有透明模式，半透明的材质按混合处理，贴图的透明度由渲染时决定
+        if material.dissolve < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+        material.specular = specular;
+        material.shininess = specular_strength;
+        material.ambient = ambient;
+        material.diffuse_map = diffuse_map;
+        let id = model.insert_material(material);
+        for t in &triangles[next_face..next_face + face_count] {
+            model
+                .faces
+                .push((t.map(|i| (i, Some(i), Some(i))), Some(id)));
+        }
+        next_face += face_count;
+    }
+    // 不属于任何材质的三角形
+    for t in &triangles[next_face..] {
+        model.faces.push((t.map(|i| (i, Some(i), Some(i))), None));
+    }
+    model.smoothing_groups = vec![None; model.faces.len()];
+    Ok(model)
+}
+
+// 全局信息中各类索引大小的位置
+const VERTEX: usize = 0;
+const TEXTURE: usize = 1;
+const BONE: usize = 3;
+
+/// 小端字节流读取器
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// 文本编码，false为UTF-16LE
+    utf8: bool,
+    /// 顶点、贴图、材质、骨骼、表情、刚体索引的字节数
+    index_sizes: [u8; 6],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            utf8: false,
+            index_sizes: [4; 6],
+        }
+    }
+
+    fn invalid(&self, what: &'static str, offset: usize) -> PmxError {
+        PmxError::InvalidValue { what, offset }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], PmxError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(PmxError::UnexpectedEof { offset: self.pos })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PmxError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, PmxError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn i32(&mut self) -> Result<i32, PmxError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, PmxError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    /// 非负的元素个数
+    fn count(&mut self) -> Result<usize, PmxError> {
+        let offset = self.pos;
+        usize::try_from(self.i32()?).map_err(|_| self.invalid("count", offset))
+    }
+
+    fn vec2(&mut self) -> Result<Vector2<f32>, PmxError> {
+        Ok(Vector2::new([self.f32()?, self.f32()?]))
+    }
+
+    fn vec3(&mut self) -> Result<Vector3<f32>, PmxError> {
+        Ok(Vector3::new([self.f32()?, self.f32()?, self.f32()?]))
+    }
+
+    fn vec4(&mut self) -> Result<Vector4<f32>, PmxError> {
+        Ok(Vector4::new([
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+        ]))
+    }
+
+    fn text(&mut self) -> Result<String, PmxError> {
+        let len = self.count()?;
+        let bytes = self.bytes(len)?;
+        Ok(if self.utf8 {
+            String::from_utf8_lossy(bytes).into_owned()
+        } else {
+            let units = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        })
+    }
+
+    /// 顶点索引：1、2字节时为无符号数
+    fn vertex_index(&mut self) -> Result<usize, PmxError> {
+        let offset = self.pos;
+        let i = match self.index_sizes[VERTEX] {
+            1 => self.u8()? as i64,
+            2 => u16::from_le_bytes(self.array()?) as i64,
+            _ => self.i32()? as i64,
+        };
+        usize::try_from(i).map_err(|_| self.invalid("vertex index", offset))
+    }
+
+    /// 其他索引：有符号数，-1表示无
+    fn index(&mut self, kind: usize) -> Result<Option<usize>, PmxError> {
+        let i = match self.index_sizes[kind] {
+            1 => i8::from_le_bytes(self.array()?) as i32,
+            2 => i16::from_le_bytes(self.array()?) as i32,
+            _ => self.i32()?,
+        };
+        Ok(usize::try_from(i).ok())
+    }
+
+    /// 跳过顶点的蒙皮权重：类型之后是若干骨骼索引和若干浮点数
+    fn skip_deform(&mut self, version: f32) -> Result<(), PmxError> {
+        let offset = self.pos;
+        let (bones, floats) = match self.u8()? {
+            // BDEF1
+            0 => (1, 0),
+            // BDEF2，第一根骨骼的权重
+            1 => (2, 1),
+            // BDEF4，4个权重
+            2 => (4, 4),
+            // SDEF，权重以及C、R0、R1
+            3 => (2, 10),
+            // QDEF(2.1)
+            4 if version >= 2.1 => (4, 4),
+            _ => return Err(self.invalid("weight deform type", offset)),
+        };
+        self.bytes(bones * self.index_sizes[BONE] as usize + floats * 4)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// 按PMX格式拼装测试数据，索引大小全部为2字节，文本使用UTF-16LE
+    #[derive(Default)]
+    struct Writer(Vec<u8>);
+
+    impl Writer {
+        fn u8(&mut self, v: u8) -> &mut Self {
+            self.0.push(v);
+            self
+        }
+        fn i16(&mut self, v: i16) -> &mut Self {
+            self.0.extend(v.to_le_bytes());
+            self
+        }
+        fn i32(&mut self, v: i32) -> &mut Self {
+            self.0.extend(v.to_le_bytes());
+            self
+        }
+        fn f32s(&mut self, v: &[f32]) -> &mut Self {
+            v.iter().for_each(|f| self.0.extend(f.to_le_bytes()));
+            self
+        }
+        fn text(&mut self, s: &str) -> &mut Self {
+            let units = s.encode_utf16().collect::<Vec<_>>();
+            self.i32(units.len() as i32 * 2);
+            units.iter().for_each(|u| self.0.extend(u.to_le_bytes()));
+            self
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut w = Writer::default();
+        w.0.extend(b"PMX ");
+        // UTF-16LE，1个追加UV
+        w.f32s(&[2.0]).u8(8).u8(0).u8(1);
+        [2, 2, 2, 2, 2, 2].iter().for_each(|&s| {
+            w.u8(s);
+        });
+        w.text("模型").text("model").text("").text("");
+        // 4个顶点
+        w.i32(4);
+        for (i, p) in [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .iter()
+            .enumerate()
+        {
+            w.f32s(&[p[0], p[1], 0.0, 0.0, 0.0, 1.0, p[0], p[1]]);
+            w.f32s(&[0.0; 4]);
+            // 各种蒙皮权重的长度不同
+            match i {
+                0 => w.u8(0).i16(0),
+                3 => w.u8(3).i16(0).i16(1).f32s(&[0.25]).f32s(&[0.0; 9]),
+                _ => w.u8(1).i16(0).i16(1).f32s(&[0.25]),
+            };
+            w.f32s(&[1.0]);
+        }
+        // 两个三角形
+        w.i32(6);
+        [0, 1, 2, 0, 2, 3].iter().for_each(|&i| {
+            w.i16(i);
+        });
+        w.i32(2).text("tex\\体.png").text("toon.bmp");
+        // 两个同名材质各占一个三角形
+        w.i32(2);
+        for (toon_shared, edge) in [(true, 0x10), (false, 0x01)] {
+            w.text("体").text("body");
+            w.f32s(&[1.0, 0.5, 0.25, 0.5, 0.1, 0.1, 0.1, 8.0, 0.2, 0.2, 0.2]);
+            w.u8(edge).f32s(&[0.0, 0.0, 0.0, 1.0, 1.5]);
+            w.i16(0).i16(-1).u8(0);
+            if toon_shared {
+                w.u8(1).u8(3);
+            } else {
+                w.u8(0).i16(1);
+            }
+            w.text("").i32(3);
+        }
+        // 骨骼、表情等数据不读取
+        w.i32(0).i32(0);
+        w.0
+    }
+
+    #[test]
+    fn test_parse_pmx() {
+        let model = parse_pmx(&sample(), Path::new("assets")).unwrap();
+        assert_eq!(model.vertexs_count(), 4);
+        assert_eq!(model.faces_count(), 2);
+        assert_eq!(model.get_uv(3).y(), 0.0);
+        let names = model
+            .materials()
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["体", "体#2"]);
+        assert_eq!(
+            model.get_face(1),
+            (
+                [
+                    (0, Some(0), Some(0)),
+                    (2, Some(2), Some(2)),
+                    (3, Some(3), Some(3))
+                ],
+                Some(1)
+            )
+        );
+        let m = model.get_material(0);
+        assert_eq!((m.dissolve, m.shininess), (0.5, 8.0));
+        assert_eq!(m.alpha_mode, AlphaMode::Blend);
+        assert_eq!(m.diffuse_map, Some(PathBuf::from("assets/tex/体.png")));
+        // 卡通渲染贴图不作为漫反射贴图
+        assert_eq!(
+            model.get_material(1).diffuse_map,
+            Some(PathBuf::from("assets/tex/体.png"))
+        );
+    }
+
+    #[test]
+    fn test_truncated_pmx() {
+        let data = sample();
+        // 截断在最后一个材质的面片数中，其后的骨骼、表情个数不读取
+        let e = parse_pmx(&data[..data.len() - 12], Path::new("")).unwrap_err();
+        assert!(matches!(e, PmxError::UnexpectedEof { .. }));
+        assert!(matches!(
+            parse_pmx(b"PMD ", Path::new("")),
+            Err(PmxError::InvalidMagic)
+        ));
+        // 顶点数远超实际数据时在读取中途报错，而不是一次分配巨大的内存
+        let mut data = sample();
+        data[47..51].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(parse_pmx(&data, Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use crate::{
+    draw_target::{Color, LinearColor},
+    srgb,
+    vec::Vector2,
+};
+
+use super::Texture;
+
+/// 纹理过滤方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// 取最近的纹素
+    #[default]
+    Nearest,
+    /// 取周围4个纹素双线性插值
+    Bilinear,
+    /// 在相邻两级mipmap上分别双线性插值，再按细节级别插值
+    Trilinear,
+}
+
+/// 纹素坐标超出贴图范围时的处理方式，u、v两个方向分别设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap {
+    /// 平铺重复，与OBJ等格式的约定一致
+    #[default]
+    Repeat,
+    /// 镜像重复
+    MirroredRepeat,
+    /// 取边缘的纹素
+    ClampToEdge,
+    /// 取采样器的边框颜色
+    ClampToBorder,
+}
+
+impl Wrap {
+    /// 把纹素坐标映射到[0, size)内，ClampToBorder超出范围时返回None
+    pub(super) fn apply(self, i: i32, size: i32) -> Option<i32> {
+        match self {
+            Wrap::Repeat => Some(i.rem_euclid(size)),
+            Wrap::MirroredRepeat => {
+                let i = i.rem_euclid(2 * size);
+                Some(if i < size { i } else { 2 * size - 1 - i })
+            }
+            Wrap::ClampToEdge => Some(i.clamp(0, size - 1)),
+            Wrap::ClampToBorder => (0..size).contains(&i).then_some(i),
+        }
+    }
+}
+
+/// 贴图及逐级缩小一半直到1x1的mipmap链，第0级为原图
+#[derive(Debug)]
+pub struct MipChain {
+    levels: Vec<Texture>,
+    /// 纹素是否为sRGB编码，是则解码到线性空间后再过滤
+    srgb: bool,
+}
+
+impl MipChain {
+    /// 由sRGB编码的颜色贴图自动生成各级mipmap，每级由上一级2x2纹素在线性空间中取平均得到
+    pub fn new(base: Texture) -> Self {
+        Self::generate(base, true)
+    }
+
+    /// 由法线、粗糙度等按线性值存储的数据贴图生成mipmap
+    pub fn linear(base: Texture) -> Self {
+        Self::generate(base, false)
+    }
+
+    fn generate(base: Texture, srgb: bool) -> Self {
+        let mut levels = vec![base];
+        loop {
+            let last = levels.last().unwrap();
+            let (w, h) = (last.get_width(), last.get_height());
+            if w <= 1 && h <= 1 {
+                break;
+            }
+            let mut next = Texture::new((w / 2).max(1), (h / 2).max(1));
+            for y in 0..next.get_height() {
+                for x in 0..next.get_width() {
+                    let mut sum = [0.0; 4];
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let (x, y) = (2 * x + dx, 2 * y + dy);
+                        let c = decode(*last.get(x.min(w - 1), y.min(h - 1)), srgb);
+                        sum = [0, 1, 2, 3].map(|i| sum[i] + c[i] / 4.0);
+                    }
+                    next.set(x, y, encode(sum, srgb));
+                }
+            }
+            levels.push(next);
+        }
+        Self { levels, srgb }
+    }
+
+    /// 原图
+    pub fn base(&self) -> &Texture {
+        &self.levels[0]
+    }
+
+    pub fn levels(&self) -> &[Texture] {
+        &self.levels
+    }
+}
+
+/// 纹理采样器，决定如何从贴图中取出uv处的颜色
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sampler {
+    pub filter: Filter,
+    /// u方向的环绕方式
+    pub wrap_u: Wrap,
+    /// v方向的环绕方式
+    pub wrap_v: Wrap,
+    /// ClampToBorder时超出范围取的颜色，默认为透明黑色
+    pub border_color: Color,
+    /// 各向异性过滤的最大采样次数，不超过16，0和1表示关闭
+    pub max_anisotropy: u32,
+}
+
+impl Sampler {
+    /// 指定过滤方式，两个方向都平铺重复
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    /// 两个方向使用相同的环绕方式
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self {
+            wrap_u: wrap,
+            wrap_v: wrap,
+            ..self
+        }
+    }
+
+    /// 按过滤方式采样，返回线性空间中的颜色，放大时(细节级别为0)只用原图
+    /// duv_dx、duv_dy为屏幕上x、y方向移动一个像素时uv的变化量，由光栅化时求出
+    /// 开启各向异性过滤时，沿像素在贴图上覆盖区域的长轴多次采样取平均，
+    /// 细节级别按短轴选择，斜看的表面不会过于模糊
+    pub fn sample(
+        &self,
+        texture: &MipChain,
+        uv: Vector2<f32>,
+        duv_dx: Vector2<f32>,
+        duv_dy: Vector2<f32>,
+    ) -> LinearColor {
+        let (x, y) = footprint(texture, duv_dx, duv_dy);
+        let (major, minor, axis) = if x >= y {
+            (x, y, duv_dx)
+        } else {
+            (y, x, duv_dy)
+        };
+        let max_probes = self.max_anisotropy.clamp(1, 16);
+        // 放大时一个像素不到一个纹素，不需要各向异性；短轴不到一个纹素时按一个纹素计算长宽比
+        let probes = if max_probes > 1 && major > 1.0 {
+            (major / minor.max(1.0)).ceil().min(max_probes as f32) as u32
+        } else {
+            1
+        };
+        if probes == 1 {
+            let [r, g, b, a] = self.sample_level(texture, uv, level_of(texture, major));
+            return LinearColor::new(r, g, b, a);
+        }
+
+        let lod = level_of(texture, major / probes as f32);
+        let mut sum = [0.0; 4];
+        for i in 0..probes {
+            // 采样点均匀分布在长轴上
+            let t = (i as f32 + 0.5) / probes as f32 - 0.5;
+            let c = self.sample_level(texture, uv + axis * t, lod);
+            sum = [0, 1, 2, 3].map(|i| sum[i] + c[i] / probes as f32);
+        }
+        let [r, g, b, a] = sum;
+        LinearColor::new(r, g, b, a)
+    }
+
+    /// 在指定的细节级别上按过滤方式采样，返回线性空间中的颜色
+    fn sample_level(&self, texture: &MipChain, uv: Vector2<f32>, lod: f32) -> [f32; 4] {
+        match self.filter {
+            Filter::Nearest => self.nearest(texture, lod.round() as usize, uv),
+            Filter::Bilinear => self.bilinear(texture, lod.round() as usize, uv),
+            Filter::Trilinear => {
+                let level = lod.floor() as usize;
+                let t = lod - level as f32;
+                let a = self.bilinear(texture, level, uv);
+                if t > 0.0 && level + 1 < texture.levels.len() {
+                    lerp(a, self.bilinear(texture, level + 1, uv), t)
+                } else {
+                    a
+                }
+            }
+        }
+    }
+
+    /// 按环绕方式取第level级的一个纹素
+    fn texel(&self, texture: &MipChain, level: usize, x: i32, y: i32) -> [f32; 4] {
+        let t = &texture.levels[level];
+        match (
+            self.wrap_u.apply(x, t.get_width()),
+            self.wrap_v.apply(y, t.get_height()),
+        ) {
+            (Some(x), Some(y)) => decode(*t.get(x, y), texture.srgb),
+            _ => decode(self.border_color, texture.srgb),
+        }
+    }
+
+    fn nearest(&self, texture: &MipChain, level: usize, uv: Vector2<f32>) -> [f32; 4] {
+        let (x, y) = texel_coord(&texture.levels[level], uv);
+        self.texel(texture, level, x.floor() as i32, y.floor() as i32)
+    }
+
+    fn bilinear(&self, texture: &MipChain, level: usize, uv: Vector2<f32>) -> [f32; 4] {
+        // 纹素中心位于整数坐标加0.5处
+        let (x, y) = texel_coord(&texture.levels[level], uv);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let (x1, y1) = (x0.saturating_add(1), y0.saturating_add(1));
+        let texel = |x, y| self.texel(texture, level, x, y);
+        let top = lerp(texel(x0, y0), texel(x1, y0), tx);
+        let bottom = lerp(texel(x0, y1), texel(x1, y1), tx);
+        lerp(top, bottom, ty)
+    }
+}
+
+/// 屏幕上x、y方向移动一个像素时，在原图上跨过的纹素数
+fn footprint(texture: &MipChain, duv_dx: Vector2<f32>, duv_dy: Vector2<f32>) -> (f32, f32) {
+    let base = texture.base();
+    let (w, h) = (base.get_width() as f32, base.get_height() as f32);
+    let texels = |d: Vector2<f32>| (d.x() * w).hypot(d.y() * h);
+    (texels(duv_dx), texels(duv_dy))
+}
+
+/// 一个像素覆盖rho个纹素时的细节级别，0为原图，每加1纹理缩小一半
+/// 退化的三角形可能求出NaN或无穷大的导数，这时按原图采样
+fn level_of(texture: &MipChain, rho: f32) -> f32 {
+    if !rho.is_finite() {
+        return 0.0;
+    }
+    rho.log2().clamp(0.0, (texture.levels.len() - 1) as f32)
+}
+
+/// uv坐标转换为纹素坐标，v轴朝上而纹素行朝下
+fn texel_coord(texture: &Texture, uv: Vector2<f32>) -> (f32, f32) {
+    (
+        uv.x() * texture.get_width() as f32,
+        (1.0 - uv.y()) * texture.get_height() as f32,
+    )
+}
+
+/// 纹素转换为0~1的浮点数，sRGB编码的颜色分量解码到线性空间，透明度本身就是线性的
+fn decode(c: Color, srgb: bool) -> [f32; 4] {
+    let rgb = |v: u8| {
+        if srgb {
+            srgb::to_linear(v)
+        } else {
+            v as f32 / 255.0
+        }
+    };
+    [rgb(c.r), rgb(c.g), rgb(c.b), c.a as f32 / 255.0]
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// decode的逆变换
+fn encode(c: [f32; 4], srgb: bool) -> Color {
+    let unorm = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    let rgb = |v: f32| if srgb { srgb::from_linear(v) } else { unorm(v) };
+    Color {
+        r: rgb(c[0]),
+        g: rgb(c[1]),
+        b: rgb(c[2]),
+        a: unorm(c[3]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> Texture {
+        let mut texture = Texture::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                let v = if (x + y) % 2 == 0 { 255 } else { 0 };
+                texture.set(
+                    x,
+                    y,
+                    Color {
+                        r: v,
+                        g: v,
+                        b: v,
+                        a: 255,
+                    },
+                );
+            }
+        }
+        texture
+    }
+
+    #[test]
+    fn test_mip_chain() {
+        let chain = MipChain::linear(checker());
+        let sizes = chain
+            .levels()
+            .iter()
+            .map(|t| (t.get_width(), t.get_height()))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(4, 4), (2, 2), (1, 1)]);
+        // 棋盘格缩小后为灰色
+        assert_eq!(chain.levels()[1].get(0, 0).r, 128);
+        assert_eq!(chain.levels()[2].get(0, 0).r, 128);
+        // 颜色贴图在线性空间中取平均，线性的0.5编码为sRGB后更亮
+        let chain = MipChain::new(checker());
+        assert_eq!(chain.levels()[1].get(0, 0).r, 188);
+        assert_eq!(chain.levels()[2].get(0, 0).r, 188);
+    }
+
+    #[test]
+    fn test_filters() {
+        let chain = MipChain::linear(checker());
+        let uv = Vector2::new([0.125, 0.875]);
+        let (zero, one) = (Vector2::new_zero(), Vector2::new([0.25, 0.0]));
+        let sample = |filter, d| Sampler::new(filter).sample(&chain, uv, d, d);
+
+        // 放大时取原图的纹素，纹素中心处双线性插值结果与最近纹素相同
+        assert_eq!(sample(Filter::Nearest, zero).r, 1.0);
+        assert_eq!(sample(Filter::Bilinear, zero).r, 1.0);
+        // 两个纹素的中点取平均
+        let mid =
+            Sampler::new(Filter::Bilinear).sample(&chain, Vector2::new([0.25, 0.875]), zero, zero);
+        assert_eq!(mid.r, 0.5);
+
+        // 一个像素覆盖1个纹素时仍为第0级，覆盖4个纹素时为第2级
+        let lod = |dx, dy| {
+            let (x, y) = footprint(&chain, dx, dy);
+            level_of(&chain, x.max(y))
+        };
+        assert_eq!(lod(one, one), 0.0);
+        assert_eq!(lod(one * 4.0, one), 2.0);
+        // 三线性过滤在两级之间插值
+        let d = Vector2::new([0.25 * 2f32.powf(0.5), 0.0]);
+        assert!((sample(Filter::Trilinear, d).r - 0.75).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_degenerate_derivatives() {
+        // 只有一级的贴图，导数为0或NaN时都只取原图
+        let mut texture = Texture::new(1, 1);
+        texture.set(
+            0,
+            0,
+            Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+        );
+        let chain = MipChain::linear(texture);
+        let uv = Vector2::new([0.5, 0.5]);
+        let nan = Vector2::new([f32::NAN, f32::NAN]);
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::Trilinear] {
+            let sampler = Sampler {
+                max_anisotropy: 16,
+                ..Sampler::new(filter)
+            };
+            for d in [Vector2::new_zero(), nan] {
+                let c = sampler.sample(&chain, uv, d, d);
+                assert_eq!((c.r, c.g, c.a), (1.0, 0.0, 1.0));
+            }
+        }
+        assert_eq!(level_of(&chain, f32::NAN), 0.0);
+        assert_eq!(level_of(&chain, f32::INFINITY), 0.0);
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(Wrap::Repeat.apply(-1, 4), Some(3));
+        assert_eq!(Wrap::Repeat.apply(9, 4), Some(1));
+        assert_eq!(Wrap::MirroredRepeat.apply(-1, 4), Some(0));
+        assert_eq!(Wrap::MirroredRepeat.apply(5, 4), Some(2));
+        assert_eq!(Wrap::ClampToEdge.apply(-3, 4), Some(0));
+        assert_eq!(Wrap::ClampToEdge.apply(7, 4), Some(3));
+        assert_eq!(Wrap::ClampToBorder.apply(4, 4), None);
+
+        let chain = MipChain::linear(checker());
+        let zero = Vector2::new_zero();
+        let sample = |sampler: Sampler, u: f32, v: f32| {
+            sampler.sample(&chain, Vector2::new([u, v]), zero, zero).r
+        };
+        // uv为1、负数或大于1时不会越界，平铺时与对应的[0, 1)内的点相同
+        let repeat = Sampler::new(Filter::Nearest);
+        for (u, v) in [
+            (1.0, 1.0),
+            (-0.125, 0.5),
+            (2.375, -3.0),
+            (f32::MAX, f32::MIN),
+        ] {
+            sample(repeat, u, v);
+        }
+        assert_eq!(sample(repeat, 1.125, 0.875), sample(repeat, 0.125, 0.875));
+        assert_eq!(sample(repeat, -0.125, 0.875), sample(repeat, 0.875, 0.875));
+        let bilinear = Sampler::new(Filter::Bilinear);
+        assert_eq!(sample(bilinear, 1.0, 0.875), 0.5);
+
+        let border = Sampler {
+            border_color: Color {
+                r: 7,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            ..Sampler::new(Filter::Nearest).with_wrap(Wrap::ClampToBorder)
+        };
+        assert_eq!(sample(border, 1.5, 0.5), 7.0 / 255.0);
+        let edge = Sampler::new(Filter::Nearest).with_wrap(Wrap::ClampToEdge);
+        assert_eq!(sample(edge, 1.5, 0.875), sample(edge, 0.875, 0.875));
+        let mirror = Sampler::new(Filter::Nearest).with_wrap(Wrap::MirroredRepeat);
+        assert_eq!(sample(mirror, 1.125, 0.875), sample(mirror, 0.875, 0.875));
+    }
+
+    #[test]
+    fn test_anisotropic() {
+        // 竖条纹，u方向每个纹素黑白交替
+        let mut stripes = Texture::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = if x % 2 == 0 { 255 } else { 0 };
+                let c = Color {
+                    r: v,
+                    g: v,
+                    b: v,
+                    a: 255,
+                };
+                stripes.set(x, y, c);
+            }
+        }
+        let chain = MipChain::linear(stripes);
+        // 斜看时一个像素在u方向跨过8个纹素，v方向只跨过1个
+        let (dx, dy) = (Vector2::new([0.5, 0.0]), Vector2::new([0.0, 1.0 / 16.0]));
+        let uv = Vector2::new([1.0 / 32.0, 0.5]);
+        let isotropic = Sampler::new(Filter::Trilinear);
+        let anisotropic = Sampler {
+            max_anisotropy: 16,
+            ..isotropic
+        };
+        // 两种方式都把条纹平均为灰色
+        let gray = |c: LinearColor| (0.47..=0.53).contains(&c.r);
+        assert!(gray(isotropic.sample(&chain, uv, dx, dy)));
+        assert!(gray(anisotropic.sample(&chain, uv, dx, dy)));
+        // 放大时即使一个方向的变化量为0也只采样一次，与各向同性过滤相同
+        let magnified = anisotropic.sample(&chain, uv, dx / 16.0, Vector2::new_zero());
+        assert_eq!(magnified.r, 1.0);
+
+        // 横条纹在v方向变化，各向同性过滤按长轴选了很小的mipmap而模糊，
+        // 各向异性过滤沿u方向多次采样，仍能分辨出条纹
+        let mut rows = Texture::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let v = if y / 2 % 2 == 0 { 255 } else { 0 };
+                let c = Color {
+                    r: v,
+                    g: v,
+                    b: v,
+                    a: 255,
+                };
+                rows.set(x, y, c);
+            }
+        }
+        let chain = MipChain::linear(rows);
+        let uv = Vector2::new([0.5, 1.0 - 0.5 / 16.0]);
+        assert!(isotropic.sample(&chain, uv, dx, dy).r < 0.75);
+        assert_eq!(anisotropic.sample(&chain, uv, dx, dy).r, 1.0);
+    }
+}
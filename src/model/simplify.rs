@@ -0,0 +1,531 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::vec::Vector3;
+
+use super::{normals::safe_normalize, FaceVertex, Model, SubMesh};
+
+/// 边界、UV接缝、法向量折痕和材质分界处的约束平面的权重
+const FEATURE_WEIGHT: f64 = 10.0;
+
+/// 简化的停止条件，任一条件满足即停止
+#[derive(Debug, Clone, Copy)]
+pub struct SimplifyOptions {
+    /// 目标面片数
+    pub target_faces: usize,
+    /// 允许的最大几何误差（模型坐标单位），不限制时为`f32::INFINITY`
+    pub max_error: f32,
+}
+
+/// 一级简化后的模型
+#[derive(Debug)]
+pub struct Lod {
+    pub model: Model,
+    /// 相对原模型的几何误差上界（模型坐标单位）
+    pub error: f32,
+}
+
+/// 由精细到粗糙排列的各级简化模型，不含原模型
+#[derive(Debug)]
+pub struct LodChain {
+    /// 原模型包围球的半径，用于把误差换算到屏幕上
+    radius: f32,
+    pub levels: Vec<Lod>,
+}
+
+impl LodChain {
+    /// 按模型在屏幕上的大小选择层级
+    /// `projected_size`为包围球投影到屏幕上的直径（像素），见`BoundingSphere::projected_size`
+    /// 返回误差投影到屏幕上不超过`max_pixel_error`的最粗糙的层级在`levels`中的序号，都不满足时返回None，即使用原模型
+    pub fn select(&self, projected_size: f32, max_pixel_error: f32) -> Option<usize> {
+        let pixels_per_unit = projected_size / (2.0 * self.radius);
+        self.levels
+            .iter()
+            .rposition(|lod| lod.error * pixels_per_unit <= max_pixel_error)
+    }
+}
+
+impl Model {
+    /// 用二次误差度量(QEM)的半边折叠简化模型，顶点只会合并到已有顶点上，UV和法向量沿用原有的值
+    /// 模型边界、UV接缝、法向量折痕和材质分界上的顶点只能沿着这些边折叠，从而保持其形状
+    /// 平直着色的模型每个顶点都在折痕上，需要先生成平滑法向量
+    /// 内嵌贴图不会保留，原模型有切线时按保留下来的面片重新生成，部件按保留下来的面片重新计算范围
+    pub fn simplify(&self, options: SimplifyOptions) -> Lod {
+        let mut simplifier = Simplifier::new(self);
+        let max_cost = (options.max_error as f64).powi(2);
+        let mut error = 0.0f64;
+        while simplifier.alive_count > options.target_faces {
+            let Some(Collapse {
+                cost,
+                from,
+                to,
+                versions,
+            }) = simplifier.heap.pop()
+            else {
+                break;
+            };
+            if versions != (simplifier.versions[from], simplifier.versions[to]) {
+                continue;
+            }
+            if cost > max_cost {
+                break;
+            }
+            // 入队之后周围的拓扑可能已经改变，重新检查
+            let Some(attributes) = simplifier.attribute_map(from, to) else {
+                continue;
+            };
+            simplifier.collapse(from, to, &attributes);
+            error = error.max(cost);
+        }
+        Lod {
+            model: simplifier.finish(),
+            error: error.sqrt() as f32,
+        }
+    }
+
+    /// 依次按各级选项在上一级的基础上简化，生成LOD链，误差逐级累加
+    pub fn generate_lods(&self, levels: &[SimplifyOptions]) -> LodChain {
+        let mut chain = LodChain {
+            radius: self
+                .bounding_sphere()
+                .map_or(1.0, |s| s.radius.max(f32::EPSILON)),
+            levels: Vec::new(),
+        };
+        for &options in levels {
+            let source = chain.levels.last().map_or(self, |lod| &lod.model);
+            let mut lod = source.simplify(options);
+            lod.error += chain.levels.last().map_or(0.0, |lod| lod.error);
+            chain.levels.push(lod);
+        }
+        chain
+    }
+}
+
+/// 对称4x4矩阵的上三角部分，v^T Q v 为点到一组平面的距离平方和
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// 过点p、单位法向量为n的平面
+    fn plane(n: Vector3<f32>, p: Vector3<f32>, weight: f64) -> Self {
+        let [a, b, c] = [n.x(), n.y(), n.z()].map(f64::from);
+        let d = -(a * p.x() as f64 + b * p.y() as f64 + c * p.z() as f64);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (q, o) in self.0.iter_mut().zip(other.0) {
+            *q += o;
+        }
+    }
+
+    fn error(&self, p: Vector3<f32>) -> f64 {
+        let [x, y, z] = [p.x(), p.y(), p.z()].map(f64::from);
+        let q = &self.0;
+        let e = q[0] * x * x
+            + 2.0 * (q[1] * x * y + q[2] * x * z + q[3] * x)
+            + q[4] * y * y
+            + 2.0 * (q[5] * y * z + q[6] * y)
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        e.max(0.0)
+    }
+}
+
+/// 把顶点from折叠到顶点to上，代价最小的先出队
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    /// 入队时两个顶点的版本，顶点改变后该项作废
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// from的一种(UV, 法向量, 材质)到to的(UV, 法向量)的映射
+type AttributeMap = HashMap<(Option<usize>, Option<usize>, Option<usize>), FaceAttributes>;
+type FaceAttributes = (Option<usize>, Option<usize>);
+
+struct Simplifier<'a> {
+    model: &'a Model,
+    faces: Vec<([FaceVertex; 3], Option<usize>)>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    /// 每个顶点坐标所在的面片，可能包含已删除的面片
+    adjacency: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(model: &'a Model) -> Self {
+        let n = model.vertexs.len();
+        let mut s = Self {
+            model,
+            faces: model.faces.clone(),
+            alive: vec![true; model.faces.len()],
+            alive_count: model.faces.len(),
+            adjacency: vec![Vec::new(); n],
+            quadrics: vec![Quadric::default(); n],
+            versions: vec![0; n],
+            heap: BinaryHeap::new(),
+        };
+
+        let mut edges = HashMap::<(usize, usize), Vec<usize>>::new();
+        for (i, (face, _)) in model.faces.iter().enumerate() {
+            let [a, b, c] = face.map(|v| v.0);
+            // 退化面片直接丢弃
+            if a == b || b == c || c == a {
+                s.alive[i] = false;
+                s.alive_count -= 1;
+                continue;
+            }
+            let q = Quadric::plane(s.face_normal(i), model.vertexs[a], 1.0);
+            for v in [a, b, c] {
+                s.adjacency[v].push(i);
+                s.quadrics[v].add(&q);
+            }
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                edges.entry((u.min(v), u.max(v))).or_default().push(i);
+            }
+        }
+
+        // 特征边上加入过边且垂直于面片的约束平面，偏离这些边的折叠代价变大
+        for (&(u, v), faces) in &edges {
+            if !s.is_feature_edge(u, v, faces) {
+                continue;
+            }
+            let (pu, pv) = (model.vertexs[u], model.vertexs[v]);
+            for &f in faces {
+                let n = safe_normalize(s.face_normal(f).cross(pv - pu));
+                let q = Quadric::plane(n, pu, FEATURE_WEIGHT);
+                s.quadrics[u].add(&q);
+                s.quadrics[v].add(&q);
+            }
+        }
+        // 按固定顺序入队，代价相同时结果不随哈希表的顺序变化
+        let mut keys = edges.into_keys().collect::<Vec<_>>();
+        keys.sort_unstable();
+        for (u, v) in keys {
+            s.push(u, v);
+            s.push(v, u);
+        }
+        s
+    }
+
+    fn face_normal(&self, face: usize) -> Vector3<f32> {
+        let [a, b, c] = self.faces[face].0.map(|v| self.model.vertexs[v.0]);
+        safe_normalize((b - a).cross(c - a))
+    }
+
+    fn corner(&self, face: usize, vertex: usize) -> FaceVertex {
+        *self.faces[face].0.iter().find(|c| c.0 == vertex).unwrap()
+    }
+
+    /// 边界、非流形边，或两侧面片的材质或端点属性不同的边
+    fn is_feature_edge(&self, u: usize, v: usize, faces: &[usize]) -> bool {
+        let [f, g] = faces[..] else {
+            return true;
+        };
+        self.faces[f].1 != self.faces[g].1
+            || self.corner(f, u) != self.corner(g, u)
+            || self.corner(f, v) != self.corner(g, v)
+    }
+
+    fn alive_faces(&self, vertex: usize) -> impl Iterator<Item = usize> + '_ {
+        self.adjacency[vertex]
+            .iter()
+            .copied()
+            .filter(|&f| self.alive[f])
+    }
+
+    /// 能否折叠在出队时才检查
+    fn push(&mut self, from: usize, to: usize) {
+        let mut q = self.quadrics[from];
+        q.add(&self.quadrics[to]);
+        self.heap.push(Collapse {
+            cost: q.error(self.model.vertexs[to]),
+            from,
+            to,
+            versions: (self.versions[from], self.versions[to]),
+        });
+    }
+
+    /// 检查from能否折叠到to上，可以时返回from各面片顶点的属性应替换成的to的属性
+    fn attribute_map(&self, from: usize, to: usize) -> Option<AttributeMap> {
+        let mut map = AttributeMap::new();
+        let mut shared = Vec::new();
+        for f in self.alive_faces(from) {
+            if !self.faces[f].0.iter().any(|c| c.0 == to) {
+                continue;
+            }
+            let (_, uv, n) = self.corner(f, from);
+            let (_, to_uv, to_n) = self.corner(f, to);
+            let key = (uv, n, self.faces[f].1);
+            // 边两侧面片在from处属性相同、在to处不同时，to处有接缝，不能沿这条边折叠
+            if *map.entry(key).or_insert((to_uv, to_n)) != (to_uv, to_n) {
+                return None;
+            }
+            shared.push(f);
+        }
+        if shared.is_empty() {
+            return None;
+        }
+
+        // 两个顶点共同的邻接顶点只能是边两侧面片的第三个顶点，否则折叠后会产生非流形
+        let neighbors = |v: usize| {
+            let mut ns = self
+                .alive_faces(v)
+                .flat_map(|f| self.faces[f].0.map(|c| c.0))
+                .filter(|&w| w != v)
+                .collect::<Vec<_>>();
+            ns.sort_unstable();
+            ns.dedup();
+            ns
+        };
+        let to_neighbors = neighbors(to);
+        let common = neighbors(from)
+            .into_iter()
+            .filter(|w| to_neighbors.binary_search(w).is_ok())
+            .count();
+        if common != shared.len() {
+            return None;
+        }
+
+        let target = self.model.vertexs[to];
+        for f in self.alive_faces(from) {
+            let (face, material) = &self.faces[f];
+            if face.iter().any(|c| c.0 == to) {
+                continue;
+            }
+            let (_, uv, n) = self.corner(f, from);
+            // from处的每种属性都要在边两侧的面片中出现，否则会把接缝或材质分界拉离原位
+            if !map.contains_key(&(uv, n, *material)) {
+                return None;
+            }
+            // 折叠后面片不能翻转或退化
+            let old = self.face_normal(f);
+            let [a, b, c] = face.map(|c| {
+                if c.0 == from {
+                    target
+                } else {
+                    self.model.vertexs[c.0]
+                }
+            });
+            let new = (b - a).cross(c - a);
+            if new.norm2() <= f32::EPSILON * f32::EPSILON || safe_normalize(new).dot(old) < 0.2 {
+                return None;
+            }
+        }
+        Some(map)
+    }
+
+    fn collapse(&mut self, from: usize, to: usize, attributes: &AttributeMap) {
+        for f in std::mem::take(&mut self.adjacency[from]) {
+            if !self.alive[f] {
+                continue;
+            }
+            let (face, material) = &mut self.faces[f];
+            if face.iter().any(|c| c.0 == to) {
+                self.alive[f] = false;
+                self.alive_count -= 1;
+                continue;
+            }
+            for c in face.iter_mut().filter(|c| c.0 == from) {
+                let (uv, n) = attributes[&(c.1, c.2, *material)];
+                *c = (to, uv, n);
+            }
+            self.adjacency[to].push(f);
+        }
+        let q = self.quadrics[from];
+        self.quadrics[to].add(&q);
+        self.versions[from] += 1;
+        self.versions[to] += 1;
+
+        let alive = &self.alive;
+        self.adjacency[to].retain(|&f| alive[f]);
+        let mut neighbors = self
+            .alive_faces(to)
+            .flat_map(|f| self.faces[f].0.map(|c| c.0))
+            .filter(|&w| w != to)
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        for w in neighbors {
+            self.push(w, to);
+            self.push(to, w);
+        }
+    }
+
+    /// 只保留未删除的面片和被引用的顶点坐标，面片保持原有顺序
+    fn finish(self) -> Model {
+        let model = self.model;
+        let mut vertex_ids = vec![None; model.vertexs.len()];
+        let mut simplified = Model {
+            normals: model.normals.clone(),
+            texture_vertexs: model.texture_vertexs.clone(),
+            materials: model.materials.clone(),
+            material_ids: model.material_ids.clone(),
+            ..Default::default()
+        };
+        // 原面片序号之前保留下来的面片数，用于换算部件的范围
+        let mut kept_before = Vec::with_capacity(self.faces.len() + 1);
+        for (i, (face, material)) in self.faces.iter().enumerate() {
+            kept_before.push(simplified.faces.len());
+            if !self.alive[i] {
+                continue;
+            }
+            let face = face.map(|(v, uv, n)| {
+                let id = *vertex_ids[v].get_or_insert_with(|| {
+                    simplified.vertexs.push(model.vertexs[v]);
+                    if let Some(&color) = model.colors.get(v) {
+                        simplified.colors.push(color);
+                    }
+                    simplified.vertexs.len() - 1
+                });
+                (id, uv, n)
+            });
+            simplified.faces.push((face, *material));
+            simplified.smoothing_groups.push(model.smoothing_group(i));
+        }
+        kept_before.push(simplified.faces.len());
+        simplified.sub_meshes = model
+            .sub_meshes
+            .iter()
+            .map(|s| SubMesh {
+                faces: kept_before[s.faces.start]..kept_before[s.faces.end],
+                ..s.clone()
+            })
+            .filter(|s| !s.faces.is_empty())
+            .collect();
+        if model.has_tangents() {
+            simplified.generate_tangents();
+        }
+        simplified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vec::Vector2;
+
+    use super::*;
+
+    /// xy平面上n×n格的单位正方形网格，x<0.5的面片使用材质0，其余使用材质1
+    fn grid(n: usize) -> Model {
+        let mut model = Model::default();
+        let id = |x: usize, y: usize| y * (n + 1) + x;
+        for y in 0..=n {
+            for x in 0..=n {
+                let p = [x as f32 / n as f32, y as f32 / n as f32];
+                model.vertexs.push(Vector3::new([p[0], p[1], 0.0]));
+                model.texture_vertexs.push(Vector2::new(p));
+            }
+        }
+        model.normals.push(Vector3::new([0.0, 0.0, 1.0]));
+        let materials = [0, 1].map(|i| model.material_id_or_insert(&i.to_string()));
+        for y in 0..n {
+            for x in 0..n {
+                let c = |x, y| (id(x, y), Some(id(x, y)), Some(0));
+                let m = Some(materials[usize::from(x >= n / 2)]);
+                model
+                    .faces
+                    .push(([c(x, y), c(x + 1, y), c(x + 1, y + 1)], m));
+                model
+                    .faces
+                    .push(([c(x, y), c(x + 1, y + 1), c(x, y + 1)], m));
+            }
+        }
+        model.smoothing_groups = vec![None; model.faces.len()];
+        model
+    }
+
+    fn area(model: &Model) -> f32 {
+        (0..model.faces_count())
+            .map(|i| {
+                let [a, b, c] = model.get_face(i).0.map(|v| model.get_vertex(v.0));
+                (b - a).cross(c - a).norm() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_simplify_preserves_boundaries() {
+        let model = grid(8);
+        let lod = model.simplify(SimplifyOptions {
+            target_faces: 0,
+            max_error: 1e-4,
+        });
+        assert!(lod.model.faces_count() < 16, "{}", lod.model.faces_count());
+        assert!(lod.error <= 1e-4);
+        assert!((area(&lod.model) - 1.0).abs() < 1e-4);
+        // 材质分界x=0.5保持不变
+        for i in 0..lod.model.faces_count() {
+            let (face, m) = lod.model.get_face(i);
+            let xs = face.map(|v| lod.model.get_vertex(v.0).x());
+            if m == model.find_material("0") {
+                assert!(xs.iter().all(|&x| x <= 0.5));
+            } else {
+                assert!(xs.iter().all(|&x| x >= 0.5));
+            }
+            // UV跟随顶点坐标
+            for v in face {
+                let p = lod.model.get_vertex(v.0);
+                assert_eq!(lod.model.get_uv(v.1.unwrap()), Vector2::new([p.x(), p.y()]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lod_chain() {
+        let model = grid(8);
+        let levels = [64, 16].map(|target_faces| SimplifyOptions {
+            target_faces,
+            max_error: f32::INFINITY,
+        });
+        let chain = model.generate_lods(&levels);
+        assert_eq!(chain.levels.len(), 2);
+        assert!(chain.levels[0].model.faces_count() <= 64);
+        assert!(chain.levels[1].model.faces_count() <= 16);
+        assert!(chain.levels[0].error <= chain.levels[1].error);
+        // 平面网格简化没有误差，任何大小都可以用最粗糙的层级
+        assert_eq!(chain.select(1000.0, 1.0), Some(1));
+    }
+}
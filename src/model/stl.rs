@@ -0,0 +1,210 @@
+use std::{collections::HashMap, error::Error, fmt, fs, io, path::Path};
+
+use crate::vec::Vector3;
+
+use super::{normals::safe_normalize, Model};
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(io::Error),
+    /// 二进制STL的三角形数量与文件长度不符
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    /// ASCII STL第line行的内容无法解析
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Truncated { expected, actual } => {
+                write!(f, "truncated stl: expected {expected} bytes, got {actual}")
+            }
+            Self::InvalidLine { line, text } => write!(f, "{line}: invalid stl line `{text}`"),
+        }
+    }
+}
+
+impl Error for StlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StlError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Model {
+    /// 加载ASCII或二进制STL，每个面片使用文件中的面法向量，法向量为零时由顶点计算
+    /// 坐标完全相同的顶点合并为一个，便于之后生成平滑法向量
+    pub fn load_from_stl(filename: impl AsRef<Path>) -> Result<Self, StlError> {
+        parse_stl(&fs::read(filename)?)
+    }
+}
+
+fn parse_stl(data: &[u8]) -> Result<Model, StlError> {
+    let mut builder = StlBuilder::default();
+    // 二进制STL的文件头也可能以solid开头，先按长度判断，再看文件头中有没有ASCII STL不会出现的0字节
+    let binary_len = data
+        .get(80..84)
+        .map(|n| 84 + 50 * u32::from_le_bytes(n.try_into().unwrap()) as usize);
+    let ascii =
+        data.trim_ascii_start().starts_with(b"solid") && !data[..data.len().min(84)].contains(&0);
+    if binary_len == Some(data.len()) || !ascii {
+        let expected = binary_len.unwrap_or(84);
+        if expected != data.len() {
+            return Err(StlError::Truncated {
+                expected,
+                actual: data.len(),
+            });
+        }
+        for facet in data[84..].chunks_exact(50) {
+            let f = |i: usize| f32::from_le_bytes(facet[i * 4..i * 4 + 4].try_into().unwrap());
+            let v = |i: usize| Vector3::new([f(i), f(i + 1), f(i + 2)]);
+            builder.facet(v(0), [v(3), v(6), v(9)]);
+        }
+    } else {
+        parse_ascii(&String::from_utf8_lossy(data), &mut builder)?;
+    }
+    let mut model = builder.model;
+    model.smoothing_groups = vec![None; model.faces.len()];
+    Ok(model)
+}
+
+fn parse_ascii(s: &str, builder: &mut StlBuilder) -> Result<(), StlError> {
+    let mut normal = Vector3::new_zero();
+    let mut corners = Vec::with_capacity(3);
+    for (i, text) in s.lines().enumerate() {
+        let invalid = || StlError::InvalidLine {
+            line: i + 1,
+            text: text.trim().to_string(),
+        };
+        let mut tokens = text.split_whitespace();
+        let vector = |tokens: &mut std::str::SplitWhitespace| {
+            let mut xyz = [0.0; 3];
+            for c in &mut xyz {
+                *c = tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(invalid)?;
+            }
+            Ok::<_, StlError>(Vector3::new(xyz))
+        };
+        match tokens.next() {
+            Some("facet") => {
+                if tokens.next() != Some("normal") {
+                    return Err(invalid());
+                }
+                normal = vector(&mut tokens)?;
+                corners.clear();
+            }
+            Some("vertex") => corners.push(vector(&mut tokens)?),
+            Some("endfacet") => {
+                // 多于3个顶点的面片按扇形三角化
+                for k in 1..corners.len().saturating_sub(1) {
+                    builder.facet(normal, [corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct StlBuilder {
+    model: Model,
+    /// 按坐标的比特去重
+    vertex_ids: HashMap<[u32; 3], usize>,
+}
+
+impl StlBuilder {
+    fn facet(&mut self, normal: Vector3<f32>, corners: [Vector3<f32>; 3]) {
+        let model = &mut self.model;
+        let normal = if normal.norm2() > 0.0 {
+            normal.normalize()
+        } else {
+            let [a, b, c] = corners;
+            safe_normalize((b - a).cross(c - a))
+        };
+        model.normals.push(normal);
+        let n = model.normals.len() - 1;
+        let face = corners.map(|p| {
+            let id = *self
+                .vertex_ids
+                .entry([p.x(), p.y(), p.z()].map(f32::to_bits))
+                .or_insert_with(|| {
+                    model.vertexs.push(p);
+                    model.vertexs.len() - 1
+                });
+            (id, None, Some(n))
+        });
+        model.faces.push((face, None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_stl() {
+        let s = "solid cube\n\
+                 facet normal 0 0 2\n\
+                 outer loop\n\
+                 vertex 0 0 0\n\
+                 vertex 1 0 0\n\
+                 vertex 1 1 0\n\
+                 endloop\n\
+                 endfacet\n\
+                 facet normal 0 0 0\n\
+                 outer loop\n\
+                 vertex 0 0 0\n\
+                 vertex 1 1 0\n\
+                 vertex 0 1 0\n\
+                 endloop\n\
+                 endfacet\n\
+                 endsolid cube\n";
+        let model = parse_stl(s.as_bytes()).unwrap();
+        assert_eq!((model.vertexs_count(), model.faces_count()), (4, 2));
+        for i in 0..2 {
+            let n = model.get_normal(model.get_face(i).0[0].2.unwrap());
+            assert_eq!([n.x(), n.y(), n.z()], [0.0, 0.0, 1.0]);
+        }
+
+        let e = parse_stl(b"solid x\nvertex 1 2\n").unwrap_err();
+        assert!(matches!(e, StlError::InvalidLine { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_binary_stl() {
+        // 文件头以solid开头的二进制STL
+        let mut data = b"solid binary".to_vec();
+        data.resize(80, 0);
+        data.extend(1u32.to_le_bytes());
+        for f in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            data.extend(f.to_le_bytes());
+        }
+        data.extend(0u16.to_le_bytes());
+        let model = parse_stl(&data).unwrap();
+        assert_eq!((model.vertexs_count(), model.faces_count()), (3, 1));
+        assert_eq!(model.get_vertex(2).y(), 1.0);
+
+        let e = parse_stl(&data[..data.len() - 1]).unwrap_err();
+        assert!(matches!(e, StlError::Truncated { .. }));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use crate::vec::{Vector3, Vector4};
+
+use super::{normals::safe_normalize, FaceVertex, Model};
+
+impl Model {
+    /// 是否已生成切线
+    pub fn has_tangents(&self) -> bool {
+        !self.faces.is_empty() && self.tangents.len() == self.faces.len() * 3
+    }
+
+    /// 获取面片顶点的切线，xyz为切线方向，w为副切线的手性(±1)
+    pub fn get_tangent(&self, face: usize, corner: usize) -> Vector4<f32> {
+        self.tangents[face * 3 + corner]
+    }
+
+    /// 获取面片顶点的副切线，由法向量、切线及手性求出
+    pub fn get_bitangent(&self, face: usize, corner: usize) -> Vector3<f32> {
+        let t = self.get_tangent(face, corner);
+        let n = self.corner_normal(face, corner);
+        n.cross(Vector3::new([t.x(), t.y(), t.z()])) * t.w()
+    }
+
+    /// 按MikkTSpace的约定生成每个面片顶点的切线，用于切线空间法线贴图
+    /// 坐标、UV、法向量都相同且手性一致的面片顶点共享同一切线，按顶点处的内角加权
+    /// 法向量改变后需要重新生成
+    pub fn generate_tangents(&mut self) {
+        // 每个面片由UV梯度求出的切线、副切线方向
+        let frames = (0..self.faces.len())
+            .map(|i| self.face_tangent_frame(i))
+            .collect::<Vec<_>>();
+        // UV镜像的面片手性相反，不能与未镜像的面片共享切线
+        let flips = frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                frame.is_some_and(|(t, b)| self.corner_normal(i, 0).cross(t).dot(b) < 0.0)
+            })
+            .collect::<Vec<_>>();
+
+        let mut sums = HashMap::<(FaceVertex, bool), (Vector3<f32>, Vector3<f32>)>::new();
+        for (i, (face, _)) in self.faces.iter().enumerate() {
+            let Some((t, b)) = frames[i] else {
+                continue;
+            };
+            let n = (0..3).map(|j| self.corner_normal(i, j)).collect::<Vec<_>>();
+            for j in 0..3 {
+                let p = self.vertexs[face[j].0];
+                let e1 = self.vertexs[face[(j + 1) % 3].0] - p;
+                let e2 = self.vertexs[face[(j + 2) % 3].0] - p;
+                let angle = safe_normalize(e1)
+                    .dot(safe_normalize(e2))
+                    .clamp(-1.0, 1.0)
+                    .acos();
+                // 先投影到顶点法向量的切平面上再累加
+                let t = safe_normalize(t - n[j] * n[j].dot(t)) * angle;
+                let b = safe_normalize(b - n[j] * n[j].dot(b)) * angle;
+                let sum = sums
+                    .entry((face[j], flips[i]))
+                    .or_insert((Vector3::new_zero(), Vector3::new_zero()));
+                sum.0 += t;
+                sum.1 += b;
+            }
+        }
+
+        let mut tangents = Vec::with_capacity(self.faces.len() * 3);
+        for (i, (face, _)) in self.faces.iter().enumerate() {
+            for (j, v) in face.iter().enumerate() {
+                let n = self.corner_normal(i, j);
+                let (t, b) = sums
+                    .get(&(*v, flips[i]))
+                    .copied()
+                    .unwrap_or((Vector3::new_zero(), Vector3::new_zero()));
+                // Gram-Schmidt正交化，退化时取任意一个与法向量垂直的方向
+                let mut t = t - n * n.dot(t);
+                if t.norm2() <= f32::EPSILON {
+                    t = perpendicular(n);
+                }
+                let t = t.normalize();
+                let w = if n.cross(t).dot(b) < 0.0 { -1.0 } else { 1.0 };
+                tangents.push(Vector4::new([t.x(), t.y(), t.z(), w]));
+            }
+        }
+        self.tangents = tangents;
+    }
+
+    /// 已生成切线时按当前的坐标、UV和法向量重新生成，修改网格后调用
+    pub(super) fn refresh_tangents(&mut self) {
+        if self.has_tangents() {
+            self.generate_tangents();
+        }
+    }
+
+    /// 面片顶点的法向量，缺省时使用面片法向量
+    pub(super) fn corner_normal(&self, face: usize, corner: usize) -> Vector3<f32> {
+        self.faces[face].0[corner]
+            .2
+            .map(|n| self.normals[n])
+            .unwrap_or_else(|| self.get_face_normal(face))
+    }
+
+    /// 由坐标和UV的偏导求面片的切线与副切线，缺少UV或UV退化时返回None
+    fn face_tangent_frame(&self, index: usize) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let face = &self.faces[index].0;
+        let [p0, p1, p2] = face.map(|v| self.vertexs[v.0]);
+        let uvs = [face[0].1?, face[1].1?, face[2].1?];
+        let [uv0, uv1, uv2] = uvs.map(|i| self.texture_vertexs[i]);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let (d1, d2) = (uv1 - uv0, uv2 - uv0);
+        let det = d1.x() * d2.y() - d2.x() * d1.y();
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = (e1 * d2.y() - e2 * d1.y()) / det;
+        let b = (e2 * d1.x() - e1 * d2.x()) / det;
+        Some((safe_normalize(t), safe_normalize(b)))
+    }
+}
+
+/// 取一个与n垂直的方向
+fn perpendicular(n: Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x().abs() < 0.9 {
+        Vector3::new([1.0, 0.0, 0.0])
+    } else {
+        Vector3::new([0.0, 1.0, 0.0])
+    };
+    axis - n * n.dot(axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        model::{simplify::SimplifyOptions, NormalMode},
+        vec::Vector2,
+    };
+
+    use super::*;
+
+    /// xy平面上的单位正方形，UV的u方向为`u_sign * x`
+    fn quad(u_sign: f32) -> Model {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        Model {
+            vertexs: corners.map(|p| Vector3::new([p[0], p[1], 0.0])).to_vec(),
+            texture_vertexs: corners
+                .map(|p| Vector2::new([p[0] * u_sign, p[1]]))
+                .to_vec(),
+            normals: vec![Vector3::new([0.0, 0.0, 1.0])],
+            faces: vec![
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (1, Some(1), Some(0)),
+                        (2, Some(2), Some(0)),
+                    ],
+                    None,
+                ),
+                (
+                    [
+                        (0, Some(0), Some(0)),
+                        (2, Some(2), Some(0)),
+                        (3, Some(3), Some(0)),
+                    ],
+                    None,
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn xyzw(v: Vector4<f32>) -> [f32; 4] {
+        [v.x(), v.y(), v.z(), v.w()]
+    }
+
+    #[test]
+    fn test_planar_tangents() {
+        let mut model = quad(1.0);
+        assert!(!model.has_tangents());
+        model.generate_tangents();
+        assert!(model.has_tangents());
+        for (face, corner) in [(0, 0), (0, 2), (1, 2)] {
+            assert_eq!(xyzw(model.get_tangent(face, corner)), [1.0, 0.0, 0.0, 1.0]);
+            let b = model.get_bitangent(face, corner);
+            assert_eq!([b.x(), b.y(), b.z()], [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn test_mirrored_uv_handedness() {
+        let mut model = quad(-1.0);
+        model.generate_tangents();
+        assert_eq!(xyzw(model.get_tangent(1, 1)), [-1.0, 0.0, 0.0, -1.0]);
+        let b = model.get_bitangent(1, 1);
+        assert_eq!([b.x(), b.y(), b.z()], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_mutators_refresh_tangents() {
+        // 法向量向x方向倾斜，正交化后的切线也随之倾斜
+        let mut model = quad(1.0);
+        model.normals = vec![Vector3::new([0.6, 0.0, 0.8])];
+        model.generate_tangents();
+        let t = xyzw(model.get_tangent(0, 0)).map(|x| (x * 1000.0).round() / 1000.0);
+        assert_eq!(t, [0.8, 0.0, -0.6, 1.0]);
+
+        // 与在当前网格上重新生成的结果相同
+        let assert_fresh = |model: &mut Model| {
+            assert!(model.has_tangents());
+            let tangents = model.tangents.clone();
+            model.generate_tangents();
+            assert_eq!(model.tangents, tangents);
+        };
+        model.normalize_to_unit_cube();
+        assert_fresh(&mut model);
+
+        model.generate_normals(NormalMode::Flat);
+        assert!(model.has_tangents());
+        assert_eq!(xyzw(model.get_tangent(0, 0)), [1.0, 0.0, 0.0, 1.0]);
+
+        let lod = model.simplify(SimplifyOptions {
+            target_faces: 2,
+            max_error: f32::INFINITY,
+        });
+        let mut simplified = lod.model;
+        assert_fresh(&mut simplified);
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    error::Error,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::BufReader,
+    mem,
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
+};
+
+use image::{ImageFormat, ImageReader};
+
+use crate::draw_target::Color;
+
+use super::{MipChain, Texture};
+
+/// 贴图缓存，按路径在首次使用时加载并生成mipmap，多个材质、模型共用同一份贴图
+/// 路径不同但像素相同的贴图只保存一份
+/// 同一文件作为颜色贴图和作为透明度等数据贴图使用时分别保存
+#[derive(Debug, Default)]
+pub struct TextureCache {
+    /// 规范化后的路径及是否为sRGB编码到贴图的映射，贴图由`textures`持有
+    paths: HashMap<(PathBuf, bool), Weak<MipChain>>,
+    /// 像素的哈希及是否为sRGB编码到贴图的映射，哈希相同时逐个比较像素
+    textures: HashMap<(u64, bool), Vec<Rc<MipChain>>>,
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取路径对应的sRGB编码的颜色贴图，第一次使用时从文件加载
+    pub fn get(&mut self, path: impl AsRef<Path>) -> Result<Rc<MipChain>, Box<dyn Error>> {
+        self.load(path.as_ref(), true)
+    }
+
+    /// 取路径对应的线性编码的数据贴图，如透明度贴图
+    pub fn get_linear(&mut self, path: impl AsRef<Path>) -> Result<Rc<MipChain>, Box<dyn Error>> {
+        self.load(path.as_ref(), false)
+    }
+
+    fn load(&mut self, path: &Path, srgb: bool) -> Result<Rc<MipChain>, Box<dyn Error>> {
+        let key = (
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            srgb,
+        );
+        if let Some(texture) = self.paths.get(&key).and_then(Weak::upgrade) {
+            return Ok(texture);
+        }
+
+        // TGA等没有文件头标识的格式需要按扩展名判断
+        let reader = match ImageFormat::from_path(path) {
+            Ok(format) => ImageReader::with_format(BufReader::new(File::open(path)?), format),
+            Err(_) => ImageReader::open(path)?.with_guessed_format()?,
+        };
+        let texture = self.share(Texture::from_image(&reader.decode()?), srgb);
+        self.paths.insert(key, Rc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    /// 放入模型内嵌等不需要从文件加载的贴图，以path为键，像素相同的贴图同样只保存一份
+    pub fn insert(&mut self, path: impl Into<PathBuf>, texture: Texture) -> Rc<MipChain> {
+        let texture = self.share(texture, true);
+        self.paths
+            .insert((path.into(), true), Rc::downgrade(&texture));
+        texture
+    }
+
+    /// 已有像素相同的贴图时直接共用，否则生成mipmap后加入缓存
+    fn share(&mut self, texture: Texture, srgb: bool) -> Rc<MipChain> {
+        let mut hasher = DefaultHasher::new();
+        (texture.get_width(), texture.get_height()).hash(&mut hasher);
+        for c in texture.get_data() {
+            [c.r, c.g, c.b, c.a].hash(&mut hasher);
+        }
+        self.share_hashed(hasher.finish(), texture, srgb)
+    }
+
+    fn share_hashed(&mut self, hash: u64, texture: Texture, srgb: bool) -> Rc<MipChain> {
+        let bucket = self.textures.entry((hash, srgb)).or_default();
+        let same = |t: &&Rc<MipChain>| {
+            let base = t.base();
+            (base.get_width(), base.get_height()) == (texture.get_width(), texture.get_height())
+                && base.get_data() == texture.get_data()
+        };
+        if let Some(t) = bucket.iter().find(same) {
+            return t.clone();
+        }
+        let t = Rc::new(if srgb {
+            MipChain::new(texture)
+        } else {
+            MipChain::linear(texture)
+        });
+        bucket.push(t.clone());
+        t
+    }
+
+    /// 缓存中不重复的贴图数量
+    pub fn len(&self) -> usize {
+        self.textures.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// 所有贴图及其mipmap占用的字节数
+    pub fn memory_usage(&self) -> usize {
+        self.textures
+            .values()
+            .flatten()
+            .flat_map(|t| t.levels())
+            .map(|level| level.get_data().len() * mem::size_of::<Color>())
+            .sum()
+    }
+
+    /// 释放缓存之外已没有引用的贴图，返回释放的数量，之后再使用时从文件重新加载
+    /// 通过`insert`放入的贴图释放后无法重新加载，需要时应保留其引用
+    pub fn evict_unused(&mut self) -> usize {
+        let before = self.len();
+        for bucket in self.textures.values_mut() {
+            bucket.retain(|t| Rc::strong_count(t) > 1);
+        }
+        self.textures.retain(|_, bucket| !bucket.is_empty());
+        self.paths.retain(|_, t| t.strong_count() > 0);
+        before - self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn test_texture_cache() {
+        let dir =
+            std::env::temp_dir().join(format!("tinyrenderer-textures-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let red = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        red.save(dir.join("a.png")).unwrap();
+        red.save(dir.join("b.png")).unwrap();
+        RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 128]))
+            .save(dir.join("c.png"))
+            .unwrap();
+
+        let mut cache = TextureCache::new();
+        let a = cache.get(dir.join("a.png")).unwrap();
+        // 同一文件的不同写法、内容相同的不同文件共用一份贴图
+        let again = cache.get(dir.join(".").join("a.png")).unwrap();
+        let b = cache.get(dir.join("b.png")).unwrap();
+        assert!(Rc::ptr_eq(&a, &again) && Rc::ptr_eq(&a, &b));
+        let c = cache.get(dir.join("c.png")).unwrap();
+        assert!(c.base().has_alpha());
+        assert_eq!(cache.len(), 2);
+        // 4x4、2x2、1x1与2x2、1x1共26个纹素
+        assert_eq!(cache.memory_usage(), 26 * mem::size_of::<Color>());
+        assert!(cache.get(dir.join("missing.png")).is_err());
+
+        // 内嵌贴图与文件内容不同，单独保存
+        let embedded = cache.insert("model.glb#0", Texture::new(1, 1));
+        assert_eq!(cache.len(), 3);
+
+        drop((a, again, b, embedded));
+        assert_eq!(cache.evict_unused(), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(dir.join("b.png")).unwrap().base().get_width(), 4);
+        drop(c);
+        assert_eq!(cache.evict_unused(), 2);
+        assert!(cache.is_empty());
+
+        // 同一文件作为数据贴图使用时不做sRGB解码，单独保存
+        let color = cache.get(dir.join("a.png")).unwrap();
+        let data = cache.get_linear(dir.join("a.png")).unwrap();
+        assert!(!Rc::ptr_eq(&color, &data));
+        assert_eq!(cache.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_collision() {
+        let mut cache = TextureCache::new();
+        let mut other = Texture::new(1, 1);
+        other.set(
+            0,
+            0,
+            Color {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 4,
+            },
+        );
+        // 哈希相同而像素不同的贴图不会被合并
+        let a = cache.share_hashed(0, Texture::new(1, 1), true);
+        let b = cache.share_hashed(0, other, true);
+        let c = cache.share_hashed(0, Texture::new(1, 1), true);
+        assert!(!Rc::ptr_eq(&a, &b));
+        assert!(Rc::ptr_eq(&a, &c));
+        assert_eq!(cache.len(), 2);
+    }
+}
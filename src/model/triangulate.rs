@@ -0,0 +1,164 @@
+use crate::vec::{Vector2, Vector3};
+
+/// 将多边形三角化，返回每个三角形在`polygon`中的顶点序号
+/// 凸多边形直接扇形三角化，凹多边形或非平面多边形投影到最佳拟合平面后做耳切
+pub fn triangulate(polygon: &[Vector3<f32>]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    if n < 3 {
+        return Vec::new();
+    }
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let points = project(polygon);
+    // 投影后按逆时针方向统一判定凸角
+    let orientation = signed_area(&points).signum();
+    if orientation == 0.0 {
+        // 退化多边形，无法判断凹凸
+        return fan(n);
+    }
+    let corner = |a: usize, b: usize, c: usize| {
+        cross(points[b] - points[a], points[c] - points[b]) * orientation
+    };
+    if (0..n).all(|i| corner(i, (i + 1) % n, (i + 2) % n) >= 0.0) {
+        return fan(n);
+    }
+    ear_clip(&points, orientation)
+}
+
+fn fan(n: usize) -> Vec<[usize; 3]> {
+    (1..n - 1).map(|i| [0, i, i + 1]).collect()
+}
+
+fn ear_clip(points: &[Vector2<f32>], orientation: f32) -> Vec<[usize; 3]> {
+    let mut remain = (0..points.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remain.len() > 3 {
+        let m = remain.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (remain[(i + m - 1) % m], remain[i], remain[(i + 1) % m]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            // 耳朵必须是凸角，且其余顶点都不在该三角形内
+            cross(pb - pa, pc - pb) * orientation > 0.0
+                && remain
+                    .iter()
+                    .filter(|&&p| p != a && p != b && p != c)
+                    .all(|&p| !in_triangle(points[p], pa, pb, pc))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remain[(i + m - 1) % m], remain[i], remain[(i + 1) % m]]);
+                remain.remove(i);
+            }
+            // 自相交等找不到耳朵的情况，剩余部分退化为扇形
+            None => break,
+        }
+    }
+    triangles.extend((1..remain.len() - 1).map(|i| [remain[0], remain[i], remain[i + 1]]));
+    triangles
+}
+
+/// 用Newell法求多边形法向量，去掉法向量分量最大的轴投影到二维平面
+fn project(polygon: &[Vector3<f32>]) -> Vec<Vector2<f32>> {
+    let mut normal = Vector3::new_zero();
+    for (i, p) in polygon.iter().enumerate() {
+        let q = polygon[(i + 1) % polygon.len()];
+        normal += Vector3::new([
+            (p.y() - q.y()) * (p.z() + q.z()),
+            (p.z() - q.z()) * (p.x() + q.x()),
+            (p.x() - q.x()) * (p.y() + q.y()),
+        ]);
+    }
+    let (nx, ny, nz) = (normal.x().abs(), normal.y().abs(), normal.z().abs());
+    polygon
+        .iter()
+        .map(|p| {
+            if nx >= ny && nx >= nz {
+                Vector2::new([p.y(), p.z()])
+            } else if ny >= nz {
+                Vector2::new([p.z(), p.x()])
+            } else {
+                Vector2::new([p.x(), p.y()])
+            }
+        })
+        .collect()
+}
+
+fn cross(u: Vector2<f32>, v: Vector2<f32>) -> f32 {
+    u.x() * v.y() - u.y() * v.x()
+}
+
+fn signed_area(points: &[Vector2<f32>]) -> f32 {
+    (0..points.len())
+        .map(|i| cross(points[i], points[(i + 1) % points.len()]))
+        .sum::<f32>()
+        * 0.5
+}
+
+/// 判断点p是否在三角形abc内（含边界）
+fn in_triangle(p: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>) -> bool {
+    let d1 = cross(b - a, p - a);
+    let d2 = cross(c - b, p - b);
+    let d3 = cross(a - c, p - c);
+    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(has_neg && has_pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[[f32; 2]]) -> Vec<Vector3<f32>> {
+        points
+            .iter()
+            .map(|p| Vector3::new([p[0], p[1], 0.0]))
+            .collect()
+    }
+
+    fn area(polygon: &[Vector3<f32>], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|t| {
+                let (a, b, c) = (polygon[t[0]], polygon[t[1]], polygon[t[2]]);
+                (b - a).cross(c - a).z() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_quad_fan() {
+        let quad = polygon(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        assert_eq!(triangulate(&quad), [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_concave_ear_clip() {
+        // L形多边形，从顶点0出发的扇形会越过凹角(1, 1)
+        let l = polygon(&[
+            [2.0, 1.0],
+            [1.0, 1.0],
+            [1.0, 2.0],
+            [0.0, 2.0],
+            [0.0, 0.0],
+            [2.0, 0.0],
+        ]);
+        let triangles = triangulate(&l);
+        assert_eq!(triangles.len(), 4);
+        // 所有三角形与原多边形同向，且面积之和等于多边形面积
+        assert!(triangles.iter().all(|t| area(&l, &[*t]) > 0.0));
+        assert!((area(&l, &triangles) - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_non_planar_quad() {
+        let quad = vec![
+            Vector3::new([0.0, 0.0, 0.0]),
+            Vector3::new([0.0, 0.0, 1.0]),
+            Vector3::new([0.1, 1.0, 1.0]),
+            Vector3::new([0.0, 1.0, 0.0]),
+        ];
+        assert_eq!(triangulate(&quad).len(), 2);
+    }
+}
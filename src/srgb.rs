@@ -0,0 +1,68 @@
+use std::sync::OnceLock;
+
+/// 8位sRGB分量解码为0~1的线性值
+/// 贴图和显示用的8位颜色都是sRGB编码的，光照、混合、过滤需要在线性空间中计算
+pub fn to_linear(c: u8) -> f32 {
+    static LUT: OnceLock<[f32; 256]> = OnceLock::new();
+    LUT.get_or_init(|| std::array::from_fn(|i| decode(i as f32 / 255.0)))[c as usize]
+}
+
+/// 0~1的浮点sRGB分量解码为线性值，超出0~1的值截断
+/// 插值后的顶点颜色等不是8位的值，直接解码，不先量化到8位
+pub fn to_linear_f32(c: f32) -> f32 {
+    decode(c.clamp(0.0, 1.0))
+}
+
+/// 线性值编码为8位sRGB分量，超出0~1的值截断
+pub fn from_linear(v: f32) -> u8 {
+    // 线性值按4096级量化查表，暗部每级不到半个8位分量，往返转换不损失精度
+    const STEPS: usize = 4096;
+    static LUT: OnceLock<Vec<u8>> = OnceLock::new();
+    let lut = LUT.get_or_init(|| {
+        (0..STEPS)
+            .map(|i| (encode(i as f32 / (STEPS - 1) as f32) * 255.0).round() as u8)
+            .collect()
+    });
+    lut[(v.clamp(0.0, 1.0) * (STEPS - 1) as f32).round() as usize]
+}
+
+fn decode(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb() {
+        for c in 0..=255 {
+            assert_eq!(from_linear(to_linear(c)), c);
+        }
+        assert_eq!(to_linear(255), 1.0);
+        // sRGB的中灰约为线性的21.6%
+        assert!((to_linear(128) - 0.2158).abs() < 1e-3);
+        assert_eq!(from_linear(0.5), 188);
+        assert_eq!(from_linear(-1.0), 0);
+        assert_eq!(from_linear(2.0), 255);
+        assert_eq!(from_linear(f32::NAN), 0);
+
+        // 浮点分量与8位分量的解码结果一致，介于两级之间的值不会被量化
+        assert!((to_linear_f32(128.0 / 255.0) - to_linear(128)).abs() < 1e-6);
+        let between = to_linear_f32(128.5 / 255.0);
+        assert!(between > to_linear(128) && between < to_linear(129));
+        assert_eq!(to_linear_f32(2.0), 1.0);
+    }
+}
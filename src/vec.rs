@@ -2,7 +2,7 @@ use std::ops;
 
 use num_traits::{Float, Num};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vector<T: Num, const S: usize> {
     data: [T; S],
 }